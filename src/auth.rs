@@ -0,0 +1,270 @@
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use directories::BaseDirs;
+use git2::{Cred, CredentialType};
+
+use crate::secrets::Secret;
+
+pub const DEFAULT_SSH_USER: &str = "git";
+
+/// User name and token offered to the git host when cloning via https.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpsCredentials {
+    pub username: String,
+    pub token: Secret,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PassphraseSource {
+    Prompt,
+    Command(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SshKey {
+    pub private_key: PathBuf,
+    pub public_key: Option<PathBuf>,
+    pub passphrase: Option<PassphraseSource>,
+}
+
+/// SSH authentication methods of a provider. The agent is tried first, the
+/// key file afterwards.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SshAuth {
+    pub use_agent: bool,
+    pub key: Option<SshKey>,
+}
+
+impl Default for SshAuth {
+    fn default() -> Self {
+        SshAuth {
+            use_agent: true,
+            key: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CloneAuth {
+    pub https: Option<HttpsCredentials>,
+    pub ssh: SshAuth,
+}
+
+enum SshMethod<'a> {
+    Agent,
+    Key(&'a SshKey),
+}
+
+impl CloneAuth {
+    /// Builds a callback for `RemoteCallbacks::credentials`. libgit2 invokes it
+    /// again after every rejected credential, so each call offers the next
+    /// configured method until none is left.
+    pub fn credentials_callback(
+        &self,
+    ) -> impl FnMut(&str, Option<&str>, CredentialType) -> Result<Cred, git2::Error> + '_ {
+        let mut https_offered = false;
+        let mut ssh_attempts = 0;
+
+        move |_url, username_from_url, allowed_types| {
+            let username = username_from_url.unwrap_or(DEFAULT_SSH_USER);
+
+            // ssh servers that also accept passwords offer plaintext
+            // credentials as well, those still get the ssh methods below
+            if allowed_types.is_user_pass_plaintext() && !https_offered {
+                if let Some(credentials) = &self.https {
+                    https_offered = true;
+                    return Cred::userpass_plaintext(
+                        &credentials.username,
+                        credentials.token.expose(),
+                    );
+                }
+            }
+
+            if allowed_types.is_username() {
+                return Cred::username(username);
+            }
+
+            if allowed_types.is_user_pass_plaintext() && !allowed_types.is_ssh_key() {
+                return Err(git2::Error::from_str(
+                    "no (further) https credentials configured",
+                ));
+            }
+
+            let methods = self.ssh_methods();
+
+            while let Some(method) = methods.get(ssh_attempts) {
+                ssh_attempts += 1;
+
+                match method {
+                    SshMethod::Agent => return Cred::ssh_key_from_agent(username),
+                    SshMethod::Key(key) => match key.credential(username) {
+                        Ok(credential) => return Ok(credential),
+                        Err(e) => {
+                            eprintln!("Skipping ssh key '{}': {}", key.private_key.display(), e)
+                        }
+                    },
+                }
+            }
+
+            Err(git2::Error::from_str(
+                "all configured ssh authentication methods failed",
+            ))
+        }
+    }
+
+    fn ssh_methods(&self) -> Vec<SshMethod<'_>> {
+        let mut methods = vec![];
+
+        if self.ssh.use_agent {
+            methods.push(SshMethod::Agent);
+        }
+
+        if let Some(key) = &self.ssh.key {
+            methods.push(SshMethod::Key(key));
+        }
+
+        methods
+    }
+}
+
+impl SshKey {
+    fn credential(&self, username: &str) -> anyhow::Result<Cred> {
+        let passphrase = match &self.passphrase {
+            Some(source) => Some(source.resolve(self)?),
+            None => None,
+        };
+
+        let public_key = self.public_key.as_deref().map(expand_home);
+
+        let credential = Cred::ssh_key(
+            username,
+            public_key.as_deref(),
+            &expand_home(&self.private_key),
+            passphrase.as_deref(),
+        )?;
+
+        Ok(credential)
+    }
+}
+
+impl PassphraseSource {
+    fn resolve(&self, key: &SshKey) -> anyhow::Result<String> {
+        match self {
+            PassphraseSource::Prompt => {
+                let prompt = format!("Passphrase for '{}': ", key.private_key.display());
+                Ok(rpassword::prompt_password(prompt)?)
+            }
+            PassphraseSource::Command(command) => {
+                let output = Command::new("sh").arg("-c").arg(command).output()?;
+
+                if !output.status.success() {
+                    anyhow::bail!("passphrase command exited with {}", output.status);
+                }
+
+                let stdout = String::from_utf8(output.stdout)?;
+                Ok(stdout.trim_end_matches(['\r', '\n']).to_string())
+            }
+        }
+    }
+}
+
+/// Replaces a leading `~` with the home directory of the current user.
+pub fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), BaseDirs::new()) {
+        (Ok(rest), Some(dirs)) => dirs.home_dir().join(rest),
+        _ => path.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::path::PathBuf;
+
+    use git2::CredentialType;
+
+    use crate::auth::{CloneAuth, HttpsCredentials, PassphraseSource, SshAuth, SshKey};
+    use crate::secrets::Secret;
+
+    #[test]
+    pub fn should_offer_https_credentials_only_once() {
+        let auth = CloneAuth {
+            https: Some(HttpsCredentials {
+                username: "x-access-token".to_string(),
+                token: Secret::new("s3cr3t".to_string()),
+            }),
+            ssh: SshAuth::default(),
+        };
+        assert!(!format!("{:?}", auth).contains("s3cr3t"));
+
+        let mut callback = auth.credentials_callback();
+
+        let url = "https://git.acme.org/org/repo.git";
+        assert!(callback(url, None, CredentialType::USER_PASS_PLAINTEXT).is_ok());
+        assert!(callback(url, None, CredentialType::USER_PASS_PLAINTEXT).is_err());
+    }
+
+    #[test]
+    pub fn should_use_ssh_methods_when_server_also_offers_passwords() {
+        let auth = CloneAuth {
+            https: None,
+            ssh: SshAuth::default(),
+        };
+        let mut callback = auth.credentials_callback();
+
+        let url = "ssh://git@gitea.acme.org:2222/org/repo.git";
+        let allowed_types = CredentialType::SSH_KEY | CredentialType::USER_PASS_PLAINTEXT;
+
+        let agent = callback(url, Some("git"), allowed_types).unwrap();
+        let exhausted = callback(url, Some("git"), allowed_types);
+
+        assert!(agent.has_username());
+        assert_eq!(
+            exhausted.err().map(|e| e.message().to_string()),
+            Some("all configured ssh authentication methods failed".to_string())
+        );
+    }
+
+    #[test]
+    pub fn should_fall_back_from_agent_to_key_file_and_then_give_up() {
+        let auth = CloneAuth {
+            https: None,
+            ssh: SshAuth {
+                use_agent: true,
+                key: Some(SshKey {
+                    private_key: PathBuf::from("/home/jdoe/.ssh/id_ed25519"),
+                    public_key: None,
+                    passphrase: Some(PassphraseSource::Command("echo s3cr3t".to_string())),
+                }),
+            },
+        };
+        let mut callback = auth.credentials_callback();
+
+        let url = "ssh://admin@bitbucket.acme.org:7999/prj/repo.git";
+        let agent = callback(url, Some("admin"), CredentialType::SSH_KEY).unwrap();
+        let key = callback(url, Some("admin"), CredentialType::SSH_KEY).unwrap();
+        let exhausted = callback(url, Some("admin"), CredentialType::SSH_KEY);
+
+        assert!(agent.has_username());
+        assert!(key.has_username());
+        assert!(exhausted.is_err());
+    }
+
+    #[test]
+    pub fn should_read_passphrase_from_command_output() {
+        let key = SshKey {
+            private_key: PathBuf::from("~/.ssh/id_ed25519"),
+            public_key: None,
+            passphrase: None,
+        };
+
+        let passphrase = PassphraseSource::Command("printf 'pass phrase\\n'".to_string())
+            .resolve(&key)
+            .unwrap();
+
+        assert_eq!(passphrase, "pass phrase");
+    }
+}
@@ -0,0 +1,319 @@
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::core::{CloneUrl, FileProvider, GitUrlProvider, HttpProblem, HttpProvider, RepoMeta};
+use crate::files::config::Protocol;
+use crate::http::HttpSettings;
+
+#[derive(Debug, Deserialize)]
+pub struct ValueList<T> {
+    pub value: Vec<T>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Project {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Repo {
+    pub id: String,
+    pub name: String,
+    #[serde(rename = "sshUrl")]
+    pub ssh_url: Option<String>,
+    #[serde(rename = "remoteUrl")]
+    pub remote_url: Option<String>,
+    #[serde(rename = "isDisabled", default)]
+    pub is_disabled: bool,
+    /// A full ref like `refs/heads/main`
+    #[serde(rename = "defaultBranch")]
+    pub default_branch: Option<String>,
+    #[serde(rename = "isFork")]
+    pub is_fork: Option<bool>,
+    /// In bytes
+    pub size: Option<u64>,
+}
+
+pub struct AzureDevOps {
+    pub token: String,
+    pub collection_url: String,
+    pub symbol: Option<String>,
+    pub protocol: Protocol,
+    pub http: HttpSettings,
+}
+
+pub const API_VERSION: &str = "6.0";
+
+const CONTINUATION_TOKEN_HEADER: &str = "x-ms-continuationtoken";
+
+impl AzureDevOps {
+    pub fn new(token: String, collection_url: String, symbol: Option<String>) -> AzureDevOps {
+        AzureDevOps {
+            token,
+            collection_url,
+            symbol,
+            protocol: Protocol::ssh,
+            http: HttpSettings::default(),
+        }
+    }
+
+    pub fn with_protocol(mut self, protocol: Protocol) -> AzureDevOps {
+        self.protocol = protocol;
+        self
+    }
+
+    pub fn with_http(mut self, http: HttpSettings) -> AzureDevOps {
+        self.http = http;
+        self
+    }
+
+    pub fn fetch_clone_urls(&self, symbol: &str) -> anyhow::Result<Vec<CloneUrl>> {
+        let projects_url = format!("{}/_apis/projects", &self.collection_url);
+        let projects = Self::get_all_pages::<Project>(&self.http, &self.token, &projects_url)?;
+
+        let mut git_urls = vec![];
+
+        for Project { id, name } in projects {
+            let repos_url = format!("{}/{}/_apis/git/repositories", &self.collection_url, id);
+            let git_repos = Self::get_all_pages::<Repo>(&self.http, &self.token, &repos_url)?;
+
+            git_urls.extend(
+                git_repos
+                    .into_iter()
+                    .filter_map(|repo| self.clone_url_of(repo, &name, symbol)),
+            );
+        }
+
+        Ok(git_urls)
+    }
+
+    fn clone_url_of(&self, repo: Repo, project_name: &str, symbol: &str) -> Option<CloneUrl> {
+        // disabled repos can neither be fetched nor cloned
+        if repo.is_disabled {
+            return None;
+        }
+
+        let url = match self.protocol {
+            Protocol::ssh => repo.ssh_url,
+            Protocol::https => repo.remote_url,
+        }?;
+
+        let meta = RepoMeta {
+            full_name: Some(format!("{}/{}", project_name, repo.name)),
+            name: Some(repo.name),
+            default_branch: repo
+                .default_branch
+                .map(|branch| branch.trim_start_matches("refs/heads/").to_string()),
+            fork: repo.is_fork,
+            size: repo.size.map(|bytes| bytes / 1024),
+            ..RepoMeta::default()
+        };
+
+        Some(CloneUrl::new(url, symbol.to_string()).with_meta(meta))
+    }
+
+    /// Reads all pages of a list endpoint. Azure DevOps signals further pages
+    /// with a continuation token response header instead of a next link.
+    pub fn get_all_pages<T: DeserializeOwned>(
+        http: &HttpSettings,
+        token: &str,
+        url: &str,
+    ) -> anyhow::Result<Vec<T>> {
+        let mut results = vec![];
+        let mut continuation_token: Option<String> = None;
+
+        loop {
+            let mut request = http
+                .get(url)?
+                // PATs are sent as basic auth password with an empty user name
+                .basic_auth("", Some(token))
+                .param("api-version", API_VERSION);
+
+            if let Some(continuation_token) = &continuation_token {
+                request = request.param("continuationToken", continuation_token);
+            }
+
+            let response = request
+                .send()
+                .map_err(|e| HttpProblem::RequestFailed(url.to_string(), e.to_string()))?;
+
+            if !response.is_success() {
+                return Err(HttpProblem::RequestFailed(
+                    url.to_string(),
+                    format!("status: {}", response.status()),
+                )
+                .into());
+            }
+
+            continuation_token = response
+                .headers()
+                .get(CONTINUATION_TOKEN_HEADER)
+                .and_then(|value| value.to_str().ok())
+                .filter(|value| !value.is_empty())
+                .map(|value| value.to_string());
+
+            let deserialized_result = response
+                .json::<ValueList<T>>()
+                .map_err(|e| HttpProblem::DeserializationFailed(url.to_string(), e.to_string()))?;
+
+            results.extend(deserialized_result.value);
+
+            if continuation_token.is_none() {
+                break;
+            }
+        }
+
+        Ok(results)
+    }
+}
+
+impl HttpProvider for AzureDevOps {
+    fn request_from_remote(&self, symbol: &str) -> anyhow::Result<Vec<CloneUrl>> {
+        let result = self.fetch_clone_urls(symbol)?;
+        Ok(result)
+    }
+}
+
+impl FileProvider for AzureDevOps {
+    fn name(&self) -> &str {
+        "azure_devops"
+    }
+}
+
+impl GitUrlProvider for AzureDevOps {
+    fn symbol(&self) -> String {
+        self.symbol.to_owned().unwrap_or("".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::azure_devops::AzureDevOps;
+    use crate::core::{CloneUrl, RepoMeta};
+    use httpmock::prelude::*;
+    use serde_json::json;
+
+    #[test]
+    pub fn should_fetch_ssh_urls_of_all_projects_using_continuation_tokens() {
+        let server = MockServer::start();
+        let address = server.address().to_string();
+        let collection_url = format!("http://{address}/tfs/DefaultCollection");
+
+        // base64(":s3cr3t")
+        let basic_auth = "Basic OnMzY3IzdA==";
+
+        let projects_page_1_mock = server.mock(|when, then| {
+            when.method("GET")
+                .header("Authorization", basic_auth)
+                .path("/tfs/DefaultCollection/_apis/projects")
+                .query_param("api-version", "6.0")
+                .matches(|req| {
+                    !req.query_params
+                        .as_ref()
+                        .map(|params| params.iter().any(|(k, _)| k == "continuationToken"))
+                        .unwrap_or(false)
+                });
+            then.status(200)
+                .header("content-type", "application/json")
+                .header("x-ms-continuationtoken", "page-2")
+                .body(
+                    json!({ "count": 1, "value": [{ "id": "p-1", "name": "Shop" }] }).to_string(),
+                );
+        });
+
+        let projects_page_2_mock = server.mock(|when, then| {
+            when.method("GET")
+                .path("/tfs/DefaultCollection/_apis/projects")
+                .query_param("continuationToken", "page-2");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(json!({ "count": 1, "value": [{ "id": "p-2", "name": "Ops" }] }).to_string());
+        });
+
+        let shop_repos_mock = server.mock(|when, then| {
+            when.method("GET")
+                .header("Authorization", basic_auth)
+                .path("/tfs/DefaultCollection/p-1/_apis/git/repositories");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(
+                json!({
+                    "count": 2,
+                    "value": [
+                        {
+                            "id": "r-1",
+                            "name": "frontend",
+                            "sshUrl": "ssh://localhost:22/tfs/DefaultCollection/Shop/_git/frontend",
+                            "isDisabled": false,
+                            "defaultBranch": "refs/heads/main",
+                            "isFork": false,
+                            "size": 524288
+                        },
+                        {
+                            "id": "r-2",
+                            "name": "archive",
+                            "sshUrl": "ssh://localhost:22/tfs/DefaultCollection/Shop/_git/archive",
+                            "isDisabled": true
+                        }
+                    ]
+                })
+                .to_string(),
+            );
+        });
+
+        let ops_repos_mock = server.mock(|when, then| {
+            when.method("GET")
+                .path("/tfs/DefaultCollection/p-2/_apis/git/repositories");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(
+                    json!({
+                        "count": 1,
+                        "value": [{
+                            "id": "r-3",
+                            "name": "pipelines",
+                            "sshUrl": "ssh://localhost:22/tfs/DefaultCollection/Ops/_git/pipelines"
+                        }]
+                    })
+                    .to_string(),
+                );
+        });
+
+        let azure = AzureDevOps::new("s3cr3t".to_string(), collection_url, None);
+        let cloneable_urls = azure.fetch_clone_urls("AZ").unwrap();
+
+        projects_page_1_mock.assert();
+        projects_page_2_mock.assert();
+        shop_repos_mock.assert();
+        ops_repos_mock.assert();
+
+        assert_eq!(
+            cloneable_urls,
+            vec![
+                CloneUrl::new(
+                    "ssh://localhost:22/tfs/DefaultCollection/Shop/_git/frontend".to_string(),
+                    "AZ".to_string()
+                )
+                .with_meta(RepoMeta {
+                    name: Some("frontend".to_string()),
+                    full_name: Some("Shop/frontend".to_string()),
+                    default_branch: Some("main".to_string()),
+                    fork: Some(false),
+                    size: Some(512),
+                    ..RepoMeta::default()
+                }),
+                CloneUrl::new(
+                    "ssh://localhost:22/tfs/DefaultCollection/Ops/_git/pipelines".to_string(),
+                    "AZ".to_string()
+                )
+                .with_meta(RepoMeta {
+                    name: Some("pipelines".to_string()),
+                    full_name: Some("Ops/pipelines".to_string()),
+                    ..RepoMeta::default()
+                }),
+            ]
+        );
+    }
+}
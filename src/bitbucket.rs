@@ -1,7 +1,10 @@
-use attohttpc::{Method, RequestBuilder};
 use serde::Deserialize;
 
-use crate::core::{CloneUrl, FileProvider, GitUrlProvider, HttpProblem, HttpProvider};
+use crate::core::{
+    CloneUrl, FileProvider, GitUrlProvider, HttpProblem, HttpProvider, RepoMeta, UrlGroup,
+};
+use crate::files::config::{BitbucketListing, Protocol};
+use crate::http::HttpSettings;
 
 #[derive(Debug, Deserialize)]
 pub struct ProjectList {
@@ -36,9 +39,26 @@ pub struct RepoList {
 pub struct Repo {
     pub id: i32,
     pub name: String,
+    pub slug: Option<String>,
+    pub description: Option<String>,
+    pub archived: Option<bool>,
+    pub public: Option<bool>,
+    pub origin: Option<RepoRef>,
+    pub project: Option<ProjectRef>,
     pub links: LinkList,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct ProjectRef {
+    pub key: String,
+}
+
+/// The repo a fork was created from.
+#[derive(Debug, Deserialize)]
+pub struct RepoRef {
+    pub slug: String,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct LinkList {
     pub clone: Option<Vec<Link>>,
@@ -54,77 +74,176 @@ pub struct Link {
 
 pub struct Bitbucket {
     pub token: String,
-    pub projects_url: String,
+    pub api_url: String,
     pub symbol: Option<String>,
+    pub listing: BitbucketListing,
+    pub personal_user: Option<String>,
+    pub protocol: Protocol,
+    pub http: HttpSettings,
 }
 
-pub const USER_PROJECTS_PATH: &str = "/rest/api/1.0/projects";
+pub const API_PATH: &str = "/rest/api/1.0";
+
+pub const USER_PROJECTS_PATH: &str = "/projects";
+
+pub const READABLE_REPOS_PATH: &str = "/repos";
 
 impl Bitbucket {
-    pub fn new(token: String, projects_url: String, symbol: Option<String>) -> Bitbucket {
+    pub fn new(token: String, api_url: String, symbol: Option<String>) -> Bitbucket {
         Bitbucket {
             token,
-            projects_url,
+            api_url,
             symbol,
+            listing: BitbucketListing::projects,
+            personal_user: None,
+            protocol: Protocol::ssh,
+            http: HttpSettings::default(),
         }
     }
 
+    pub fn with_protocol(mut self, protocol: Protocol) -> Bitbucket {
+        self.protocol = protocol;
+        self
+    }
+
+    pub fn with_http(mut self, http: HttpSettings) -> Bitbucket {
+        self.http = http;
+        self
+    }
+
+    /// `personal_user` adds the repos of that user's personal project (`~user`),
+    /// which are never part of the project listing.
+    pub fn with_listing(
+        mut self,
+        listing: BitbucketListing,
+        personal_user: Option<String>,
+    ) -> Bitbucket {
+        self.listing = listing;
+        self.personal_user = personal_user;
+        self
+    }
+
     pub fn fetch_clone_urls(&self, symbol: &str) -> anyhow::Result<Vec<CloneUrl>> {
-        let project_list: Vec<Project> = Self::get_all_projects(&self.token, &self.projects_url)?;
-
-        let mut git_urls = vec![];
-
-        for Project {
-            key,
-            id: _,
-            name: _,
-            links: _,
-        } in project_list.iter()
-        {
-            let mut project_url = self.projects_url.clone();
-            project_url.push('/');
-            project_url.push_str(key);
-
-            let git_repos = Self::get_all_repos(&self.token, &project_url)?;
-
-            for Repo {
-                id: _,
-                name: _,
-                links,
-            } in git_repos
-            {
-                if let Some(links) = links.clone {
-                    let ssh_links: Vec<&Link> = links
-                        .iter()
-                        .filter(|link| match &link.name {
-                            Some(name) => name == "ssh",
-                            None => false,
-                        })
-                        .collect();
-
-                    // println!("clone url {}", &ssh_links[0].href);
-                    git_urls.push(CloneUrl(
-                        ssh_links[0].href.to_owned(),
-                        symbol.to_string(),
-                    ))
+        Ok(UrlGroup::flatten(self.fetch_grouped_clone_urls(symbol)?))
+    }
+
+    /// Groups the clone urls by project key, personal projects as `~user`.
+    pub fn fetch_grouped_clone_urls(&self, symbol: &str) -> anyhow::Result<Vec<UrlGroup>> {
+        let projects_url = format!("{}{}", &self.api_url, USER_PROJECTS_PATH);
+
+        let mut groups = vec![];
+
+        match self.listing {
+            BitbucketListing::projects => {
+                let project_list: Vec<Project> =
+                    Self::get_all_projects(&self.http, &self.token, &projects_url)?;
+
+                for Project {
+                    key,
+                    id: _,
+                    name: _,
+                    links: _,
+                } in project_list.iter()
+                {
+                    groups.push(UrlGroup::new(
+                        key,
+                        self.fetch_project_clone_urls(key, symbol)?,
+                    ));
+                }
+
+                if let Some(user) = &self.personal_user {
+                    let personal_key = format!("~{}", user);
+                    groups.push(UrlGroup::new(
+                        &personal_key,
+                        self.fetch_project_clone_urls(&personal_key, symbol)?,
+                    ));
+                }
+            }
+            BitbucketListing::permission => {
+                // already contains personal repos the token is allowed to read
+                let git_repos =
+                    Self::get_all_readable_repos(&self.http, &self.token, &self.api_url)?;
+
+                for repo in git_repos {
+                    let key = repo
+                        .project
+                        .as_ref()
+                        .map(|project| project.key.to_owned())
+                        .unwrap_or_default();
+
+                    if let Some(clone_url) = self.clone_url_of(repo, symbol) {
+                        match groups.iter_mut().find(|group| group.name == key) {
+                            Some(group) => group.clone_urls.push(clone_url),
+                            None => groups.push(UrlGroup::new(&key, vec![clone_url])),
+                        }
+                    }
                 }
             }
         }
 
-        // dbg!(&git_urls);
+        Ok(groups)
+    }
+
+    /// Lists the repos of a single project, e.g. `SHOP` or `~jdoe`.
+    pub fn fetch_project_clone_urls(
+        &self,
+        key: &str,
+        symbol: &str,
+    ) -> anyhow::Result<Vec<CloneUrl>> {
+        let project_url = format!("{}{}/{}", &self.api_url, USER_PROJECTS_PATH, key);
 
-        Ok(git_urls)
+        let clone_urls = Self::get_all_repos(&self.http, &self.token, &project_url)?
+            .into_iter()
+            .filter_map(|repo| self.clone_url_of(repo, symbol))
+            .collect();
+
+        Ok(clone_urls)
     }
 
-    pub fn get_all_projects(token: &str, url: &str) -> anyhow::Result<Vec<Project>> {
+    fn clone_url_of(&self, repo: Repo, symbol: &str) -> Option<CloneUrl> {
+        let link_name = match self.protocol {
+            Protocol::ssh => "ssh",
+            Protocol::https => "http",
+        };
+
+        let full_name = match (&repo.project, &repo.slug) {
+            (Some(project), Some(slug)) => Some(format!("{}/{}", project.key, slug)),
+            _ => None,
+        };
+
+        let meta = RepoMeta {
+            name: Some(repo.name),
+            full_name,
+            description: repo.description,
+            archived: repo.archived,
+            fork: Some(repo.origin.is_some()),
+            visibility: repo.public.map(|public| match public {
+                true => "public".to_string(),
+                false => "private".to_string(),
+            }),
+            ..RepoMeta::default()
+        };
+
+        repo.links
+            .clone
+            .unwrap_or_default()
+            .into_iter()
+            .find(|link| link.name.as_deref() == Some(link_name))
+            .map(|link| CloneUrl::new(link.href, symbol.to_string()).with_meta(meta))
+    }
+
+    pub fn get_all_projects(
+        http: &HttpSettings,
+        token: &str,
+        url: &str,
+    ) -> anyhow::Result<Vec<Project>> {
         let mut pages_remaining = true;
         let mut projects = vec![];
         let mut request_url = url.to_string();
 
         while pages_remaining {
-            let response = RequestBuilder::try_new(Method::GET, &request_url)
-                .map_err(|_e| HttpProblem::InvalidUrl(url.to_string()))?
-                .danger_accept_invalid_certs(true)
+            let response = http
+                .get(&request_url)?
                 .bearer_auth(token)
                 .send()
                 .map_err(|e| HttpProblem::RequestFailed(url.to_string(), e.to_string()))?;
@@ -144,12 +263,10 @@ impl Bitbucket {
             // dbg!(&deserialized_result);
 
             pages_remaining = !deserialized_result.is_last_page;
-            if pages_remaining && deserialized_result.next_page_start.is_some() {
-                request_url = format!(
-                    "{}?start={}",
-                    url,
-                    deserialized_result.next_page_start.unwrap()
-                )
+            if let (true, Some(next_page_start)) =
+                (pages_remaining, deserialized_result.next_page_start)
+            {
+                request_url = format!("{}?start={}", url, next_page_start)
             }
             projects.extend(deserialized_result.values);
             // dbg!(&projects);
@@ -158,43 +275,65 @@ impl Bitbucket {
         Ok(projects)
     }
 
-    pub fn get_all_repos(token: &str, project_url: &str) -> anyhow::Result<Vec<Repo>> {
+    pub fn get_all_repos(
+        http: &HttpSettings,
+        token: &str,
+        project_url: &str,
+    ) -> anyhow::Result<Vec<Repo>> {
+        let repos_url = project_url.to_string() + "/repos";
+
+        Self::get_repo_pages(http, token, &repos_url, &[])
+    }
+
+    /// Lists every repo the token has at least read access to, across all
+    /// projects including personal ones.
+    pub fn get_all_readable_repos(
+        http: &HttpSettings,
+        token: &str,
+        api_url: &str,
+    ) -> anyhow::Result<Vec<Repo>> {
+        let repos_url = format!("{}{}", api_url, READABLE_REPOS_PATH);
+
+        Self::get_repo_pages(http, token, &repos_url, &[("permission", "REPO_READ")])
+    }
+
+    fn get_repo_pages(
+        http: &HttpSettings,
+        token: &str,
+        repos_url: &str,
+        params: &[(&str, &str)],
+    ) -> anyhow::Result<Vec<Repo>> {
         let mut pages_remaining = true;
         let mut repos = vec![];
-        let repos_base_url = project_url.to_string() + "/repos";
-        let mut request_url = repos_base_url.clone();
+        let mut next_page_start: Option<i32> = None;
 
-        // println!("get all repos from {request_url}");
         while pages_remaining {
-            let response = RequestBuilder::try_new(Method::GET, &request_url)
-                .map_err(|_e| HttpProblem::InvalidUrl(request_url.clone()))?
-                .danger_accept_invalid_certs(true)
-                .bearer_auth(token)
+            let mut request = http.get(repos_url)?.bearer_auth(token).params(params);
+
+            if let Some(start) = next_page_start {
+                request = request.param("start", start);
+            }
+
+            let response = request
                 .send()
-                .map_err(|e| HttpProblem::RequestFailed(project_url.to_string(), e.to_string()))?;
+                .map_err(|e| HttpProblem::RequestFailed(repos_url.to_string(), e.to_string()))?;
 
             if !response.is_success() {
                 return Err(HttpProblem::RequestFailed(
-                    request_url,
+                    repos_url.to_string(),
                     format!("status: {}", response.status()),
                 )
                 .into());
             }
 
             let deserialized_result = response.json::<RepoList>().map_err(|e| {
-                HttpProblem::DeserializationFailed(project_url.to_string(), e.to_string())
+                HttpProblem::DeserializationFailed(repos_url.to_string(), e.to_string())
             })?;
 
-            // dbg!(&deserialized_result);
+            pages_remaining =
+                !deserialized_result.is_last_page && deserialized_result.next_page_start.is_some();
+            next_page_start = deserialized_result.next_page_start;
 
-            pages_remaining = !deserialized_result.is_last_page;
-            if pages_remaining && deserialized_result.next_page_start.is_some() {
-                request_url = format!(
-                    "{}?start={}",
-                    &repos_base_url,
-                    deserialized_result.next_page_start.unwrap()
-                )
-            }
             repos.extend(deserialized_result.values);
         }
 
@@ -207,6 +346,18 @@ impl HttpProvider for Bitbucket {
         let result = self.fetch_clone_urls(symbol)?;
         Ok(result)
     }
+
+    fn request_groups_from_remote(&self, symbol: &str) -> anyhow::Result<Vec<UrlGroup>> {
+        self.fetch_grouped_clone_urls(symbol)
+    }
+
+    fn request_group_from_remote(
+        &self,
+        group: &str,
+        symbol: &str,
+    ) -> Option<anyhow::Result<Vec<CloneUrl>>> {
+        Some(self.fetch_project_clone_urls(group, symbol))
+    }
 }
 
 impl FileProvider for Bitbucket {
@@ -220,3 +371,279 @@ impl GitUrlProvider for Bitbucket {
         self.symbol.to_owned().unwrap_or("".to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+
+    use crate::bitbucket::Bitbucket;
+    use crate::core::{CloneUrl, RepoMeta, UrlGroup};
+    use crate::files::config::{BitbucketListing, Protocol};
+    use httpmock::prelude::*;
+    use serde_json::json;
+
+    fn repo_list(name: &str, is_last_page: bool, next_page_start: Option<i32>) -> String {
+        json!({
+            "size": 1,
+            "isLastPage": is_last_page,
+            "nextPageStart": next_page_start,
+            "values": [{
+                "id": 1,
+                "name": name,
+                "slug": name.split('/').next_back(),
+                "project": { "key": name.split('/').next() },
+                "links": {
+                    "clone": [
+                        { "href": format!("https://localhost/scm/{name}.git"), "name": "http" },
+                        { "href": format!("ssh://git@localhost:7999/{name}.git"), "name": "ssh" }
+                    ]
+                }
+            }]
+        })
+        .to_string()
+    }
+
+    fn meta_of(name: &str) -> RepoMeta {
+        RepoMeta {
+            name: Some(name.to_string()),
+            full_name: Some(name.to_string()),
+            fork: Some(false),
+            ..RepoMeta::default()
+        }
+    }
+
+    #[test]
+    pub fn should_include_personal_repos_of_configured_user() {
+        let server = MockServer::start();
+        let address = server.address().to_string();
+        let api_url = format!("http://{address}/rest/api/1.0");
+
+        let projects_mock = server.mock(|when, then| {
+            when.method("GET")
+                .header("Authorization", "Bearer s3cr3t")
+                .path("/rest/api/1.0/projects");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(
+                    json!({
+                        "size": 1,
+                        "isLastPage": true,
+                        "values": [{ "id": 1, "key": "SHOP", "name": "Shop", "links": {} }]
+                    })
+                    .to_string(),
+                );
+        });
+
+        let project_repos_mock = server.mock(|when, then| {
+            when.method("GET").path("/rest/api/1.0/projects/SHOP/repos");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(repo_list("shop/cart", true, None));
+        });
+
+        let personal_repos_mock = server.mock(|when, then| {
+            when.method("GET")
+                .path("/rest/api/1.0/projects/~jdoe/repos");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(repo_list("~jdoe/playground", true, None));
+        });
+
+        let bitbucket = Bitbucket::new("s3cr3t".to_string(), api_url, None)
+            .with_listing(BitbucketListing::projects, Some("jdoe".to_string()));
+        let cloneable_urls = bitbucket.fetch_clone_urls("BB").unwrap();
+
+        projects_mock.assert();
+        project_repos_mock.assert();
+        personal_repos_mock.assert();
+
+        assert_eq!(
+            cloneable_urls,
+            vec![
+                CloneUrl::new(
+                    "ssh://git@localhost:7999/shop/cart.git".to_string(),
+                    "BB".to_string()
+                )
+                .with_meta(meta_of("shop/cart")),
+                CloneUrl::new(
+                    "ssh://git@localhost:7999/~jdoe/playground.git".to_string(),
+                    "BB".to_string()
+                )
+                .with_meta(meta_of("~jdoe/playground")),
+            ]
+        );
+    }
+
+    #[test]
+    pub fn should_page_through_readable_repos_in_permission_mode() {
+        let server = MockServer::start();
+        let address = server.address().to_string();
+        let api_url = format!("http://{address}/rest/api/1.0");
+
+        let page_1_mock = server.mock(|when, then| {
+            when.method("GET")
+                .header("Authorization", "Bearer s3cr3t")
+                .path("/rest/api/1.0/repos")
+                .query_param("permission", "REPO_READ")
+                .matches(|req| {
+                    !req.query_params
+                        .as_ref()
+                        .map(|params| params.iter().any(|(k, _)| k == "start"))
+                        .unwrap_or(false)
+                });
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(repo_list("shop/cart", false, Some(25)));
+        });
+
+        let page_2_mock = server.mock(|when, then| {
+            when.method("GET")
+                .path("/rest/api/1.0/repos")
+                .query_param("permission", "REPO_READ")
+                .query_param("start", "25");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(repo_list("~jdoe/playground", true, None));
+        });
+
+        let bitbucket = Bitbucket::new("s3cr3t".to_string(), api_url, None)
+            .with_listing(BitbucketListing::permission, None);
+        let cloneable_urls = bitbucket.fetch_clone_urls("").unwrap();
+
+        page_1_mock.assert();
+        page_2_mock.assert();
+
+        assert_eq!(cloneable_urls.len(), 2);
+        assert_eq!(
+            cloneable_urls.get(1),
+            Some(
+                &CloneUrl::new(
+                    "ssh://git@localhost:7999/~jdoe/playground.git".to_string(),
+                    "".to_string()
+                )
+                .with_meta(meta_of("~jdoe/playground"))
+            )
+        );
+    }
+
+    #[test]
+    pub fn should_pick_http_clone_links_when_protocol_is_https() {
+        let server = MockServer::start();
+        let address = server.address().to_string();
+        let api_url = format!("http://{address}/rest/api/1.0");
+
+        let repos_mock = server.mock(|when, then| {
+            when.method("GET")
+                .path("/rest/api/1.0/repos")
+                .query_param("permission", "REPO_READ");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(repo_list("shop/cart", true, None));
+        });
+
+        let bitbucket = Bitbucket::new("s3cr3t".to_string(), api_url, None)
+            .with_listing(BitbucketListing::permission, None)
+            .with_protocol(Protocol::https);
+        let cloneable_urls = bitbucket.fetch_clone_urls("").unwrap();
+
+        repos_mock.assert();
+
+        assert_eq!(
+            cloneable_urls,
+            vec![CloneUrl::new(
+                "https://localhost/scm/shop/cart.git".to_string(),
+                "".to_string()
+            )
+            .with_meta(meta_of("shop/cart"))]
+        );
+    }
+
+    #[test]
+    pub fn should_group_readable_repos_by_project_key() {
+        let server = MockServer::start();
+        let address = server.address().to_string();
+        let api_url = format!("http://{address}/rest/api/1.0");
+
+        let page_1_mock = server.mock(|when, then| {
+            when.method("GET")
+                .path("/rest/api/1.0/repos")
+                .query_param("permission", "REPO_READ")
+                .matches(|req| {
+                    !req.query_params
+                        .as_ref()
+                        .map(|params| params.iter().any(|(k, _)| k == "start"))
+                        .unwrap_or(false)
+                });
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(repo_list("SHOP/cart", false, Some(1)));
+        });
+
+        let page_2_mock = server.mock(|when, then| {
+            when.method("GET")
+                .path("/rest/api/1.0/repos")
+                .query_param("start", "1");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(repo_list("~jdoe/playground", true, None));
+        });
+
+        let bitbucket = Bitbucket::new("s3cr3t".to_string(), api_url, None)
+            .with_listing(BitbucketListing::permission, None);
+        let groups = bitbucket.fetch_grouped_clone_urls("BB").unwrap();
+
+        page_1_mock.assert();
+        page_2_mock.assert();
+
+        assert_eq!(
+            groups,
+            vec![
+                UrlGroup::new(
+                    "SHOP",
+                    vec![CloneUrl::new(
+                        "ssh://git@localhost:7999/SHOP/cart.git".to_string(),
+                        "BB".to_string()
+                    )
+                    .with_meta(meta_of("SHOP/cart"))]
+                ),
+                UrlGroup::new(
+                    "~jdoe",
+                    vec![CloneUrl::new(
+                        "ssh://git@localhost:7999/~jdoe/playground.git".to_string(),
+                        "BB".to_string()
+                    )
+                    .with_meta(meta_of("~jdoe/playground"))]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    pub fn should_fetch_repos_of_single_project() {
+        let server = MockServer::start();
+        let address = server.address().to_string();
+        let api_url = format!("http://{address}/rest/api/1.0");
+
+        let project_repos_mock = server.mock(|when, then| {
+            when.method("GET")
+                .header("Authorization", "Bearer s3cr3t")
+                .path("/rest/api/1.0/projects/SHOP/repos");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(repo_list("SHOP/cart", true, None));
+        });
+
+        let bitbucket = Bitbucket::new("s3cr3t".to_string(), api_url, None);
+        let cloneable_urls = bitbucket.fetch_project_clone_urls("SHOP", "").unwrap();
+
+        project_repos_mock.assert();
+
+        assert_eq!(
+            cloneable_urls,
+            vec![CloneUrl::new(
+                "ssh://git@localhost:7999/SHOP/cart.git".to_string(),
+                "".to_string()
+            )
+            .with_meta(meta_of("SHOP/cart"))]
+        );
+    }
+}
@@ -0,0 +1,317 @@
+use serde::de::{DeserializeOwned, IgnoredAny};
+use serde::Deserialize;
+
+use crate::bitbucket::Link;
+use crate::core::{CloneUrl, FileProvider, GitUrlProvider, HttpProblem, HttpProvider, RepoMeta};
+use crate::files::config::Protocol;
+use crate::http::HttpSettings;
+
+#[derive(Debug, Deserialize)]
+pub struct Page<T> {
+    pub values: Vec<T>,
+    pub next: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WorkspacePermission {
+    pub workspace: Workspace,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Workspace {
+    pub slug: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Repo {
+    pub name: String,
+    pub full_name: String,
+    pub description: Option<String>,
+    pub links: LinkList,
+    pub mainbranch: Option<Branch>,
+    pub is_private: Option<bool>,
+    /// Only present for forks
+    pub parent: Option<IgnoredAny>,
+    pub created_on: Option<String>,
+    /// In bytes
+    pub size: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Branch {
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LinkList {
+    pub clone: Option<Vec<Link>>,
+}
+
+pub struct BitbucketCloud {
+    pub username: String,
+    pub app_password: String,
+    pub api_url: String,
+    pub symbol: Option<String>,
+    pub protocol: Protocol,
+    pub http: HttpSettings,
+}
+
+pub const API_PATH: &str = "/2.0";
+
+impl BitbucketCloud {
+    pub fn new(
+        username: String,
+        app_password: String,
+        api_url: String,
+        symbol: Option<String>,
+    ) -> BitbucketCloud {
+        BitbucketCloud {
+            username,
+            app_password,
+            api_url,
+            symbol,
+            protocol: Protocol::ssh,
+            http: HttpSettings::default(),
+        }
+    }
+
+    pub fn with_protocol(mut self, protocol: Protocol) -> BitbucketCloud {
+        self.protocol = protocol;
+        self
+    }
+
+    pub fn with_http(mut self, http: HttpSettings) -> BitbucketCloud {
+        self.http = http;
+        self
+    }
+
+    pub fn fetch_clone_urls(&self, symbol: &str) -> anyhow::Result<Vec<CloneUrl>> {
+        let workspaces_url = format!("{}/user/permissions/workspaces", &self.api_url);
+        let workspaces = self.get_all_pages::<WorkspacePermission>(&workspaces_url)?;
+
+        let mut git_urls = vec![];
+
+        for WorkspacePermission {
+            workspace: Workspace { slug },
+        } in workspaces
+        {
+            let repos_url = format!("{}/repositories/{}", &self.api_url, slug);
+            let git_repos = self.get_all_pages::<Repo>(&repos_url)?;
+
+            git_urls.extend(
+                git_repos
+                    .into_iter()
+                    .filter_map(|repo| self.clone_url_of(repo, symbol)),
+            );
+        }
+
+        Ok(git_urls)
+    }
+
+    fn clone_url_of(&self, repo: Repo, symbol: &str) -> Option<CloneUrl> {
+        let link_name = match self.protocol {
+            Protocol::ssh => "ssh",
+            Protocol::https => "https",
+        };
+
+        let meta = RepoMeta {
+            name: Some(repo.name),
+            full_name: Some(repo.full_name),
+            description: repo.description,
+            default_branch: repo.mainbranch.map(|branch| branch.name),
+            fork: Some(repo.parent.is_some()),
+            created_at: repo.created_on,
+            visibility: repo.is_private.map(|is_private| match is_private {
+                true => "private".to_string(),
+                false => "public".to_string(),
+            }),
+            size: repo.size.map(|bytes| bytes / 1024),
+            ..RepoMeta::default()
+        };
+
+        repo.links
+            .clone
+            .unwrap_or_default()
+            .into_iter()
+            .find(|link| link.name.as_deref() == Some(link_name))
+            .map(|link| CloneUrl::new(link.href, symbol.to_string()).with_meta(meta))
+    }
+
+    /// Follows the `next` url of Bitbucket Cloud's paged responses until the
+    /// last page has been read.
+    pub fn get_all_pages<T: DeserializeOwned>(&self, url: &str) -> anyhow::Result<Vec<T>> {
+        let mut results = vec![];
+        let mut next_url = Some(url.to_string());
+
+        while let Some(request_url) = next_url {
+            let response = self
+                .http
+                .get(&request_url)?
+                .basic_auth(&self.username, Some(&self.app_password))
+                .send()
+                .map_err(|e| HttpProblem::RequestFailed(request_url.to_string(), e.to_string()))?;
+
+            if !response.is_success() {
+                return Err(HttpProblem::RequestFailed(
+                    request_url,
+                    format!("status: {}", response.status()),
+                )
+                .into());
+            }
+
+            let deserialized_result = response.json::<Page<T>>().map_err(|e| {
+                HttpProblem::DeserializationFailed(request_url.to_string(), e.to_string())
+            })?;
+
+            results.extend(deserialized_result.values);
+            next_url = deserialized_result.next;
+        }
+
+        Ok(results)
+    }
+}
+
+impl HttpProvider for BitbucketCloud {
+    fn request_from_remote(&self, symbol: &str) -> anyhow::Result<Vec<CloneUrl>> {
+        let result = self.fetch_clone_urls(symbol)?;
+        Ok(result)
+    }
+}
+
+impl FileProvider for BitbucketCloud {
+    fn name(&self) -> &str {
+        "bitbucket_cloud"
+    }
+}
+
+impl GitUrlProvider for BitbucketCloud {
+    fn symbol(&self) -> String {
+        self.symbol.to_owned().unwrap_or("".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::bitbucket_cloud::BitbucketCloud;
+    use crate::core::{CloneUrl, RepoMeta};
+    use httpmock::prelude::*;
+    use serde_json::json;
+
+    #[test]
+    pub fn should_fetch_ssh_urls_of_all_workspaces_following_next_links() {
+        let server = MockServer::start();
+        let address = server.address().to_string();
+        let api_url = format!("http://{address}/2.0");
+
+        // base64("jdoe:app-s3cr3t")
+        let basic_auth = "Basic amRvZTphcHAtczNjcjN0";
+
+        let workspaces_mock = server.mock(|when, then| {
+            when.method("GET")
+                .header("Authorization", basic_auth)
+                .path("/2.0/user/permissions/workspaces");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(json!({ "values": [{ "workspace": { "slug": "acme" } }] }).to_string());
+        });
+
+        let repos_page_2_url = format!("http://{address}/2.0/repositories/acme?page=2");
+
+        let repos_page_1_mock = server.mock(|when, then| {
+            when.method("GET")
+                .header("Authorization", basic_auth)
+                .path("/2.0/repositories/acme")
+                .matches(|req| {
+                    req.query_params
+                        .as_ref()
+                        .map(|params| params.is_empty())
+                        .unwrap_or(true)
+                });
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(
+                    json!({
+                        "values": [{
+                            "name": "billing",
+                            "full_name": "acme/billing",
+                            "description": "",
+                            "mainbranch": { "name": "develop", "type": "branch" },
+                            "is_private": true,
+                            "parent": { "full_name": "upstream/billing" },
+                            "created_on": "2021-03-09T14:11:02.114805+00:00",
+                            "size": 3145728,
+                            "links": {
+                                "clone": [
+                                    { "href": "https://jdoe@bitbucket.org/acme/billing.git", "name": "https" },
+                                    { "href": "git@bitbucket.org:acme/billing.git", "name": "ssh" }
+                                ]
+                            }
+                        }],
+                        "next": repos_page_2_url
+                    })
+                    .to_string(),
+                );
+        });
+
+        let repos_page_2_mock = server.mock(|when, then| {
+            when.method("GET")
+                .path("/2.0/repositories/acme")
+                .query_param("page", "2");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(
+                json!({
+                    "values": [{
+                        "name": "shop",
+                        "full_name": "acme/shop",
+                        "description": null,
+                        "links": {
+                            "clone": [{ "href": "git@bitbucket.org:acme/shop.git", "name": "ssh" }]
+                        }
+                    }]
+                })
+                .to_string(),
+            );
+        });
+
+        let bitbucket =
+            BitbucketCloud::new("jdoe".to_string(), "app-s3cr3t".to_string(), api_url, None);
+        let cloneable_urls = bitbucket.fetch_clone_urls("").unwrap();
+
+        workspaces_mock.assert();
+        repos_page_1_mock.assert();
+        repos_page_2_mock.assert();
+
+        assert_eq!(
+            cloneable_urls,
+            vec![
+                CloneUrl::new(
+                    "git@bitbucket.org:acme/billing.git".to_string(),
+                    "".to_string()
+                )
+                .with_meta(RepoMeta {
+                    name: Some("billing".to_string()),
+                    full_name: Some("acme/billing".to_string()),
+                    description: Some("".to_string()),
+                    default_branch: Some("develop".to_string()),
+                    fork: Some(true),
+                    created_at: Some("2021-03-09T14:11:02.114805+00:00".to_string()),
+                    visibility: Some("private".to_string()),
+                    size: Some(3072),
+                    ..RepoMeta::default()
+                }),
+                CloneUrl::new(
+                    "git@bitbucket.org:acme/shop.git".to_string(),
+                    "".to_string()
+                )
+                .with_meta(RepoMeta {
+                    name: Some("shop".to_string()),
+                    full_name: Some("acme/shop".to_string()),
+                    fork: Some(false),
+                    ..RepoMeta::default()
+                }),
+            ]
+        );
+    }
+}
@@ -0,0 +1,275 @@
+//! The format of the repo caches in `~/.cache/kloni`.
+//!
+//! A cache is a JSON lines file. The first line is a header carrying the
+//! schema version, every following line describes one repo:
+//!
+//! ```json
+//! {"version":1,"fingerprint":"9c3f0e51a4d2b7e8","kloni_version":"0.1.0"}
+//! {"url":"git@github.com:rust-lang/rust.git","group":"rust-lang","name":"rust","archived":false}
+//! ```
+//!
+//! The header also records the `fingerprint` of the provider settings the
+//! cache was collected with and the `kloni_version` that wrote it.
+//!
+//! `group` is the org or project the repo was found in and is omitted for
+//! providers without such a structure. `symbol` is only written for repos
+//! whose symbol differs from the provider's one, e.g. entries of command
+//! plugins that bring their own symbol. Caches written before the header was
+//! introduced hold one url per line, optionally followed by a tab and the
+//! group. They are read as version `0`.
+
+use std::io::{BufRead, Write};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::core::{CloneUrl, RepoMeta, UrlGroup};
+
+pub const VERSION: u32 = 1;
+
+pub const LEGACY_VERSION: u32 = 0;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum CacheError {
+    #[error("Cache can't be read: {0}")]
+    Unreadable(String),
+
+    #[error("Cache was written by a newer kloni (version {0}, supported up to {VERSION})")]
+    UnsupportedVersion(u32),
+
+    #[error("Cache line {0} is invalid: {1}")]
+    InvalidLine(usize, String),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Header {
+    version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    fingerprint: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    kloni_version: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    url: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    group: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    symbol: Option<String>,
+    #[serde(flatten)]
+    meta: RepoMeta,
+}
+
+/// `fingerprint` and `kloni_version` are `None` for caches written before
+/// they were recorded.
+#[derive(Debug, PartialEq)]
+pub struct CacheContent {
+    pub version: u32,
+    pub fingerprint: Option<String>,
+    pub kloni_version: Option<String>,
+    pub groups: Vec<UrlGroup>,
+}
+
+pub fn read(reader: impl BufRead, symbol: &str) -> Result<CacheContent, CacheError> {
+    let mut version: Option<u32> = None;
+    let mut fingerprint: Option<String> = None;
+    let mut kloni_version: Option<String> = None;
+    let mut groups: Vec<UrlGroup> = vec![];
+
+    for (idx, line) in reader.lines().enumerate() {
+        let line = line.map_err(|e| CacheError::Unreadable(e.to_string()))?;
+        let invalid = |reason: String| CacheError::InvalidLine(idx + 1, reason);
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let (clone_url, group_name) = match version {
+            None if line.starts_with('{') => {
+                let header =
+                    serde_json::from_str::<Header>(&line).map_err(|e| invalid(e.to_string()))?;
+
+                if header.version > VERSION {
+                    return Err(CacheError::UnsupportedVersion(header.version));
+                }
+
+                version = Some(header.version);
+                fingerprint = header.fingerprint;
+                kloni_version = header.kloni_version;
+                continue;
+            }
+            None | Some(LEGACY_VERSION) => {
+                version = Some(LEGACY_VERSION);
+
+                let (url, group_name) = line.split_once('\t').unwrap_or((&line, ""));
+                (
+                    CloneUrl::new(url.to_string(), symbol.to_string()),
+                    group_name.to_string(),
+                )
+            }
+            Some(_) => {
+                let entry =
+                    serde_json::from_str::<Entry>(&line).map_err(|e| invalid(e.to_string()))?;
+                (
+                    CloneUrl::new(entry.url, entry.symbol.unwrap_or(symbol.to_string()))
+                        .with_meta(entry.meta),
+                    entry.group,
+                )
+            }
+        };
+
+        match groups.iter_mut().find(|group| group.name == group_name) {
+            Some(group) => group.clone_urls.push(clone_url),
+            None => groups.push(UrlGroup::new(&group_name, vec![clone_url])),
+        }
+    }
+
+    Ok(CacheContent {
+        version: version.unwrap_or(VERSION),
+        fingerprint,
+        kloni_version,
+        groups,
+    })
+}
+
+pub fn write(
+    writer: &mut impl Write,
+    fingerprint: Option<&str>,
+    symbol: &str,
+    groups: &[UrlGroup],
+) -> anyhow::Result<()> {
+    let header = Header {
+        version: VERSION,
+        fingerprint: fingerprint.map(str::to_string),
+        kloni_version: Some(env!("CARGO_PKG_VERSION").to_string()),
+    };
+
+    writeln!(writer, "{}", serde_json::to_string(&header)?)?;
+
+    for group in groups {
+        for clone_url in group.clone_urls.iter() {
+            let entry = Entry {
+                url: clone_url.0.to_owned(),
+                group: group.name.to_owned(),
+                symbol: (clone_url.1 != symbol).then(|| clone_url.1.to_owned()),
+                meta: clone_url.2.to_owned(),
+            };
+
+            writeln!(writer, "{}", serde_json::to_string(&entry)?)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::cache::{read, write, CacheError, LEGACY_VERSION, VERSION};
+    use crate::core::{CloneUrl, RepoMeta, UrlGroup};
+
+    #[test]
+    pub fn should_read_what_was_written() {
+        let groups = vec![
+            UrlGroup::new(
+                "rust-lang",
+                vec![CloneUrl::new(
+                    "git@github.com:rust-lang/rust.git".to_string(),
+                    "GH".to_string(),
+                )
+                .with_meta(RepoMeta {
+                    name: Some("rust".to_string()),
+                    description: Some("Empowering everyone".to_string()),
+                    archived: Some(false),
+                    ..RepoMeta::default()
+                })],
+            ),
+            UrlGroup::new(
+                "",
+                vec![CloneUrl::new(
+                    "git@github.com:jdoe/dotfiles.git".to_string(),
+                    "GH".to_string(),
+                )],
+            ),
+        ];
+
+        let mut written = vec![];
+        write(&mut written, Some("9c3f0e51a4d2b7e8"), "GH", &groups).unwrap();
+
+        let content = read(written.as_slice(), "GH").unwrap();
+
+        assert_eq!(content.version, VERSION);
+        assert_eq!(content.fingerprint.as_deref(), Some("9c3f0e51a4d2b7e8"));
+        assert_eq!(
+            content.kloni_version.as_deref(),
+            Some(env!("CARGO_PKG_VERSION"))
+        );
+        assert_eq!(content.groups, groups);
+    }
+
+    #[test]
+    pub fn should_keep_symbols_differing_from_the_provider_symbol() {
+        let groups = vec![UrlGroup::new(
+            "",
+            vec![
+                CloneUrl::new(
+                    "git@git.acme.org:payments/ledger.git".to_string(),
+                    "CMDB".to_string(),
+                ),
+                CloneUrl::new(
+                    "git@git.acme.org:payments/gateway.git".to_string(),
+                    "CMD".to_string(),
+                ),
+            ],
+        )];
+
+        let mut written = vec![];
+        write(&mut written, None, "CMD", &groups).unwrap();
+
+        let lines = String::from_utf8(written.clone()).unwrap();
+        assert!(lines.contains("\"symbol\":\"CMDB\""));
+        assert!(!lines.contains("\"symbol\":\"CMD\""));
+
+        assert_eq!(read(written.as_slice(), "CMD").unwrap().groups, groups);
+    }
+
+    #[test]
+    pub fn should_read_plain_text_caches_as_legacy_version() {
+        let legacy = "git@ghes:org/repo.git\tORG\ngit@ghes:jdoe/dotfiles.git\n";
+
+        let content = read(legacy.as_bytes(), "").unwrap();
+
+        assert_eq!(content.version, LEGACY_VERSION);
+        assert_eq!(content.fingerprint, None);
+        assert_eq!(
+            content.groups,
+            vec![
+                UrlGroup::new(
+                    "ORG",
+                    vec![CloneUrl::new(
+                        "git@ghes:org/repo.git".to_string(),
+                        "".to_string()
+                    )]
+                ),
+                UrlGroup::new(
+                    "",
+                    vec![CloneUrl::new(
+                        "git@ghes:jdoe/dotfiles.git".to_string(),
+                        "".to_string()
+                    )]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    pub fn should_reject_caches_of_newer_versions() {
+        let cache = "{\"version\":99}\n{\"url\":\"git@ghes:org/repo.git\"}\n";
+
+        assert_eq!(
+            read(cache.as_bytes(), ""),
+            Err(CacheError::UnsupportedVersion(99))
+        );
+    }
+}
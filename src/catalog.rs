@@ -0,0 +1,214 @@
+use std::fs::read_to_string;
+
+use anyhow::Context;
+use serde::Deserialize;
+
+use crate::core::{CloneUrl, FileProvider, GitUrlProvider, HttpProblem, HttpProvider, RepoMeta};
+use crate::http::HttpSettings;
+
+#[derive(Debug, Deserialize)]
+pub struct Catalog {
+    pub repos: Vec<CatalogEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CatalogEntry {
+    pub url: String,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// Serves clone urls from a curated JSON or TOML catalog, either a local file
+/// or a document served over plain HTTP(S).
+pub struct CatalogProvider {
+    pub source: String,
+    pub token: Option<String>,
+    pub symbol: Option<String>,
+    pub http: HttpSettings,
+}
+
+impl CatalogProvider {
+    pub fn new(source: String, token: Option<String>, symbol: Option<String>) -> CatalogProvider {
+        CatalogProvider {
+            source,
+            token,
+            symbol,
+            http: HttpSettings::default(),
+        }
+    }
+
+    pub fn with_http(mut self, http: HttpSettings) -> CatalogProvider {
+        self.http = http;
+        self
+    }
+
+    pub fn fetch_clone_urls(&self, symbol: &str) -> anyhow::Result<Vec<CloneUrl>> {
+        let catalog = self.load_catalog()?;
+
+        let git_urls = catalog
+            .repos
+            .into_iter()
+            .map(|entry| {
+                CloneUrl::new(entry.url, symbol.to_string()).with_meta(RepoMeta {
+                    name: entry.name,
+                    description: entry.description,
+                    tags: entry.tags,
+                    ..RepoMeta::default()
+                })
+            })
+            .collect();
+
+        Ok(git_urls)
+    }
+
+    pub fn load_catalog(&self) -> anyhow::Result<Catalog> {
+        let content = match self.is_remote() {
+            true => self.download()?,
+            false => read_to_string(&self.source)
+                .context(format!("could not read catalog '{}'", &self.source))?,
+        };
+
+        Self::parse(&content)
+            .map_err(|e| HttpProblem::DeserializationFailed(self.source.to_string(), e).into())
+    }
+
+    fn is_remote(&self) -> bool {
+        self.source.starts_with("http://") || self.source.starts_with("https://")
+    }
+
+    fn download(&self) -> anyhow::Result<String> {
+        let url = &self.source;
+
+        let mut request = self.http.get(url)?;
+
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+
+        let response = request
+            .send()
+            .map_err(|e| HttpProblem::RequestFailed(url.to_string(), e.to_string()))?;
+
+        if !response.is_success() {
+            return Err(HttpProblem::RequestFailed(
+                url.to_string(),
+                format!("status: {}", response.status()),
+            )
+            .into());
+        }
+
+        let content = response
+            .text()
+            .map_err(|e| HttpProblem::RequestFailed(url.to_string(), e.to_string()))?;
+
+        Ok(content)
+    }
+
+    /// Tries JSON first and falls back to TOML. A leading `{` decides which
+    /// parse error is reported, since `[` may start either format.
+    fn parse(content: &str) -> Result<Catalog, String> {
+        serde_json::from_str::<Catalog>(content).or_else(|json_error| {
+            toml::from_str::<Catalog>(content).map_err(|toml_error| {
+                match content.trim_start().starts_with('{') {
+                    true => json_error.to_string(),
+                    false => toml_error.to_string(),
+                }
+            })
+        })
+    }
+}
+
+impl HttpProvider for CatalogProvider {
+    fn request_from_remote(&self, symbol: &str) -> anyhow::Result<Vec<CloneUrl>> {
+        let result = self.fetch_clone_urls(symbol)?;
+        Ok(result)
+    }
+}
+
+impl FileProvider for CatalogProvider {
+    fn name(&self) -> &str {
+        "catalog"
+    }
+}
+
+impl GitUrlProvider for CatalogProvider {
+    fn symbol(&self) -> String {
+        self.symbol.to_owned().unwrap_or("".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::catalog::CatalogProvider;
+    use crate::core::{CloneUrl, RepoMeta};
+    use httpmock::prelude::*;
+    use serde_json::json;
+
+    #[test]
+    pub fn should_parse_toml_catalog() {
+        let catalog = CatalogProvider::parse(
+            r#"
+            [[repos]]
+            url = "git@git.acme.org:platform/golden-service.git"
+            name = "golden-service"
+            description = "Service template"
+            tags = ["template", "onboarding"]
+
+            [[repos]]
+            url = "git@git.acme.org:platform/handbook.git"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(catalog.repos.len(), 2);
+        assert_eq!(catalog.repos[0].tags, vec!["template", "onboarding"]);
+        assert_eq!(catalog.repos[1].name, None);
+    }
+
+    #[test]
+    pub fn should_fetch_clone_urls_from_http_served_json_catalog() {
+        let server = MockServer::start();
+        let catalog_url = server.url("/golden-repos.json");
+
+        let catalog_mock = server.mock(|when, then| {
+            when.method("GET").path("/golden-repos.json");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(
+                    json!({
+                        "repos": [
+                            {
+                                "url": "git@git.acme.org:platform/golden-service.git",
+                                "name": "golden-service",
+                                "description": "Service template",
+                                "tags": ["template"]
+                            }
+                        ]
+                    })
+                    .to_string(),
+                );
+        });
+
+        let catalog = CatalogProvider::new(catalog_url, None, None);
+        let cloneable_urls = catalog.fetch_clone_urls("GOLD").unwrap();
+
+        catalog_mock.assert();
+
+        assert_eq!(
+            cloneable_urls,
+            vec![CloneUrl::new(
+                "git@git.acme.org:platform/golden-service.git".to_string(),
+                "GOLD".to_string()
+            )
+            .with_meta(RepoMeta {
+                name: Some("golden-service".to_string()),
+                description: Some("Service template".to_string()),
+                tags: vec!["template".to_string()],
+                ..RepoMeta::default()
+            })]
+        );
+    }
+}
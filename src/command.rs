@@ -0,0 +1,185 @@
+//! Runs an external executable and reads clone urls from its stdout.
+//!
+//! The executable prints one JSON object per line (protocol version 1):
+//!
+//! ```json
+//! {"version": 1, "url": "git@git.acme.org:team/repo.git", "symbol": "CMDB", "name": "repo", "description": "..."}
+//! ```
+//!
+//! `version` and `url` are required, `symbol` overrides the provider's symbol
+//! for that entry, `name` and `description` are optional. Empty lines are
+//! ignored. The protocol version kloni speaks is passed to the executable via
+//! the `KLONI_PROTOCOL_VERSION` environment variable.
+
+use std::process::Command;
+
+use serde::Deserialize;
+
+use crate::core::{CloneUrl, FileProvider, GitUrlProvider, HttpProvider, KloniError, RepoMeta};
+
+pub const PROTOCOL_VERSION: u32 = 1;
+
+#[derive(Debug, Deserialize)]
+pub struct CommandEntry {
+    pub version: u32,
+    pub url: String,
+    pub symbol: Option<String>,
+    pub name: Option<String>,
+    pub description: Option<String>,
+}
+
+pub struct CommandProvider {
+    pub program: String,
+    pub args: Vec<String>,
+    pub symbol: Option<String>,
+}
+
+impl CommandProvider {
+    pub fn new(program: String, args: Vec<String>, symbol: Option<String>) -> CommandProvider {
+        CommandProvider {
+            program,
+            args,
+            symbol,
+        }
+    }
+
+    pub fn fetch_clone_urls(&self, symbol: &str) -> anyhow::Result<Vec<CloneUrl>> {
+        let output = Command::new(&self.program)
+            .args(&self.args)
+            .env("KLONI_PROTOCOL_VERSION", PROTOCOL_VERSION.to_string())
+            .output()
+            .map_err(|e| KloniError::CommandFailed(self.program.to_string(), e.to_string()))?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(KloniError::CommandFailed(
+                self.program.to_string(),
+                format!("{}: {}", output.status, stderr.trim()),
+            )
+            .into());
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+
+        let mut git_urls = vec![];
+
+        for (idx, line) in stdout.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let entry = self.parse_line(idx + 1, line)?;
+            let symbol = entry.symbol.unwrap_or(symbol.to_string());
+
+            git_urls.push(CloneUrl::new(entry.url, symbol).with_meta(RepoMeta {
+                name: entry.name,
+                description: entry.description,
+                ..RepoMeta::default()
+            }))
+        }
+
+        Ok(git_urls)
+    }
+
+    fn parse_line(&self, line_number: usize, line: &str) -> Result<CommandEntry, KloniError> {
+        let invalid = |reason: String| {
+            KloniError::InvalidCommandOutput(self.program.to_string(), line_number, reason)
+        };
+
+        let entry =
+            serde_json::from_str::<CommandEntry>(line).map_err(|e| invalid(e.to_string()))?;
+
+        if entry.version != PROTOCOL_VERSION {
+            return Err(invalid(format!(
+                "unsupported protocol version {}, expected {}",
+                entry.version, PROTOCOL_VERSION
+            )));
+        }
+
+        Ok(entry)
+    }
+}
+
+impl HttpProvider for CommandProvider {
+    fn request_from_remote(&self, symbol: &str) -> anyhow::Result<Vec<CloneUrl>> {
+        let result = self.fetch_clone_urls(symbol)?;
+        Ok(result)
+    }
+}
+
+impl FileProvider for CommandProvider {
+    fn name(&self) -> &str {
+        "command"
+    }
+}
+
+impl GitUrlProvider for CommandProvider {
+    fn symbol(&self) -> String {
+        self.symbol.to_owned().unwrap_or("".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::command::CommandProvider;
+    use crate::core::{CloneUrl, RepoMeta};
+
+    fn shell(script: &str) -> CommandProvider {
+        CommandProvider::new(
+            "sh".to_string(),
+            vec!["-c".to_string(), script.to_string()],
+            None,
+        )
+    }
+
+    #[test]
+    pub fn should_read_clone_urls_from_json_lines() {
+        let provider = shell(
+            r#"echo '{"version": 1, "url": "git@git.acme.org:team/a.git"}'
+               echo
+               echo '{"version": '$KLONI_PROTOCOL_VERSION', "url": "git@git.acme.org:team/b.git", "symbol": "CMDB", "name": "b"}'"#,
+        );
+
+        let cloneable_urls = provider.fetch_clone_urls("CMD").unwrap();
+
+        assert_eq!(
+            cloneable_urls,
+            vec![
+                CloneUrl::new("git@git.acme.org:team/a.git".to_string(), "CMD".to_string()),
+                CloneUrl::new(
+                    "git@git.acme.org:team/b.git".to_string(),
+                    "CMDB".to_string()
+                )
+                .with_meta(RepoMeta {
+                    name: Some("b".to_string()),
+                    ..RepoMeta::default()
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    pub fn should_fail_on_non_zero_exit_code() {
+        let provider = shell("echo 'registry unreachable' >&2; exit 3");
+
+        let result = provider.fetch_clone_urls("");
+
+        assert_eq!(
+            format!("{}", result.unwrap_err()),
+            "Command 'sh' failed: exit status: 3: registry unreachable"
+        );
+    }
+
+    #[test]
+    pub fn should_fail_on_unsupported_protocol_version() {
+        let provider = shell(r#"echo '{"version": 2, "url": "git@git.acme.org:team/a.git"}'"#);
+
+        let result = provider.fetch_clone_urls("");
+
+        assert_eq!(
+            format!("{}", result.unwrap_err()),
+            "Command 'sh' printed an invalid line 1: unsupported protocol version 2, expected 1"
+        );
+    }
+}
@@ -1,14 +1,100 @@
-use std::{
-    fs::File,
-    io::{BufRead, BufReader, Write},
-};
+use std::{collections::HashSet, fs::File, io::BufReader, sync::OnceLock, time::Duration};
 
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-use crate::files::{file_is_empty, get_or_create_cache_file};
+use crate::{
+    auth::CloneAuth,
+    cache::{self, CacheContent},
+    files::{
+        cache_file_age, cache_file_modified, file_is_empty, get_or_create_cache_file,
+        lock_cache_file, read_cache_file, replace_cache_file, try_lock_cache_file,
+    },
+    http::HttpSettings,
+};
+
+/// A clone url, the symbol shown in front of it and what the provider told
+/// about the repo behind it.
+#[derive(Debug, PartialEq)]
+pub struct CloneUrl(pub String, pub String, pub RepoMeta);
+
+impl CloneUrl {
+    pub fn new(url: String, symbol: String) -> CloneUrl {
+        CloneUrl(url, symbol, RepoMeta::default())
+    }
+
+    pub fn with_meta(mut self, meta: RepoMeta) -> CloneUrl {
+        self.2 = meta;
+        self
+    }
+}
+
+/// Repo details kept in the cache. Providers fill in what their api offers,
+/// everything else stays `None`.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RepoMeta {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub full_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_branch: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub archived: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fork: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pushed_at: Option<String>,
+    /// `public`, `private` or `internal`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub visibility: Option<String>,
+    /// In kilobytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
 
+/// Clone urls found in one org, project or group of a provider. The cache
+/// remembers the group of each url, so a single group can be refreshed without
+/// walking the whole provider again. Providers without such a structure put
+/// everything into one unnamed group.
 #[derive(Debug, PartialEq)]
-pub struct CloneUrl(pub String, pub String);
+pub struct UrlGroup {
+    pub name: String,
+    pub clone_urls: Vec<CloneUrl>,
+}
+
+impl UrlGroup {
+    pub fn new(name: &str, clone_urls: Vec<CloneUrl>) -> UrlGroup {
+        UrlGroup {
+            name: name.to_string(),
+            clone_urls,
+        }
+    }
+
+    pub fn flatten(groups: Vec<UrlGroup>) -> Vec<CloneUrl> {
+        groups
+            .into_iter()
+            .flat_map(|group| group.clone_urls)
+            .collect()
+    }
+
+    /// Like `flatten`, but keeps only the first of several equal clone urls.
+    /// A repo may be listed in several groups, e.g. as org and starred repo.
+    pub fn flatten_unique(groups: Vec<UrlGroup>) -> Vec<CloneUrl> {
+        let mut seen_urls = HashSet::new();
+
+        Self::flatten(groups)
+            .into_iter()
+            .filter(|clone_url| seen_urls.insert(clone_url.0.to_owned()))
+            .collect()
+    }
+}
 
 #[derive(Error, Debug)]
 pub enum KloniError {
@@ -17,6 +103,27 @@ pub enum KloniError {
 
     #[error("Cache file for '{0}' is missing. This is unexpected behaviour.")]
     MissingCacheFile(String),
+
+    #[error("Command '{0}' failed: {1}")]
+    CommandFailed(String, String),
+
+    #[error("Command '{0}' printed an invalid line {1}: {2}")]
+    InvalidCommandOutput(String, usize, String),
+
+    #[error("No provider with id '{0}' is configured")]
+    UnknownProvider(String),
+
+    #[error("Provider '{0}' can't refresh single orgs or projects")]
+    GroupRefreshUnsupported(String),
+
+    #[error("No Github org or user '{0}' found: {1}")]
+    UnknownAccount(String, String),
+
+    #[error("Could not set up '{0}': {1}")]
+    ProviderUnavailable(String, String),
+
+    #[error("Could not refresh '{0}'")]
+    RefreshFailed(String),
 }
 
 #[derive(Error, Debug, PartialEq)]
@@ -29,45 +136,116 @@ pub enum HttpProblem {
 
     #[error("Can't deserialize response from '{0}': {1}")]
     DeserializationFailed(String, String),
+
+    #[error("Can't load CA bundle '{0}': {1}")]
+    InvalidCaBundle(String, String),
+
+    #[error("Can't load client certificate '{0}': {1}")]
+    InvalidClientCertificate(String, String),
+
+    #[error("Invalid proxy url '{0}': {1}")]
+    InvalidProxyUrl(String, String),
 }
 
 pub trait HttpProvider {
     fn request_from_remote(&self, symbol: &str) -> anyhow::Result<Vec<CloneUrl>>;
+
+    fn request_groups_from_remote(&self, symbol: &str) -> anyhow::Result<Vec<UrlGroup>> {
+        Ok(vec![UrlGroup::new("", self.request_from_remote(symbol)?)])
+    }
+
+    /// Requests the clone urls of a single org or project. `None` if the
+    /// provider has no such grouping.
+    fn request_group_from_remote(
+        &self,
+        _group: &str,
+        _symbol: &str,
+    ) -> Option<anyhow::Result<Vec<CloneUrl>>> {
+        None
+    }
 }
 
 pub trait FileProvider {
     fn name(&self) -> &str;
 
+    /// Identifies the settings the cached clone urls were collected with.
+    fn fingerprint(&self) -> String {
+        String::new()
+    }
+
     fn load_from_file(&self, cache_file: &File, symbol: &str) -> anyhow::Result<Vec<CloneUrl>> {
-        let buffered = BufReader::new(cache_file);
+        let groups = self.load_groups_from_file(cache_file, symbol)?;
+
+        Ok(UrlGroup::flatten_unique(groups))
+    }
+
+    fn load_groups_from_file(
+        &self,
+        cache_file: &File,
+        symbol: &str,
+    ) -> anyhow::Result<Vec<UrlGroup>> {
+        Ok(self.load_cache(cache_file, symbol)?.groups)
+    }
+
+    /// Reads the cache written by `update_file`. Caches of older kloni
+    /// versions, holding one clone url per line, are rewritten in the current
+    /// format on the fly.
+    fn load_cache(&self, cache_file: &File, symbol: &str) -> anyhow::Result<CacheContent> {
+        let content = cache::read(BufReader::new(cache_file), symbol)?;
 
-        let mut clone_urls = vec![];
+        if content.version < cache::VERSION {
+            let modified = cache_file.metadata()?.modified()?;
 
-        for line in buffered.lines() {
-            clone_urls.push(CloneUrl(line.unwrap(), symbol.to_string()))
+            // keeps the age of the cache, the urls aren't any fresher now. It's
+            // unknown which settings they were collected with, so there's no
+            // fingerprint either. A busy or failed migration is retried on the
+            // next run.
+            if let Ok(Some(lock)) = try_lock_cache_file(self.name()) {
+                if cache_file_modified(self.name()) == Some(modified) {
+                    let _ = lock.replace(|migrated| {
+                        cache::write(migrated, None, symbol, &content.groups)?;
+                        migrated.set_modified(modified)?;
+                        Ok(())
+                    });
+                }
+            }
         }
 
-        Ok(clone_urls)
+        Ok(content)
     }
 
-    fn update_file(&self, clone_urls: &[CloneUrl], cache_file: &mut File) -> anyhow::Result<()> {
-        let urls = clone_urls
-            .iter()
-            .map(|gurl| gurl.0.to_owned())
-            .collect::<Vec<String>>()
-            .join("\n");
+    /// Whether the cache was collected with other settings than the current
+    /// ones. Caches without fingerprint are trusted until their next refresh.
+    fn is_outdated(&self, content: &CacheContent) -> bool {
+        match &content.fingerprint {
+            Some(fingerprint) => *fingerprint != self.fingerprint(),
+            None => false,
+        }
+    }
 
-        write!(cache_file, "{urls}")?;
+    fn update_file(
+        &self,
+        groups: &[UrlGroup],
+        symbol: &str,
+        cache_file: &mut File,
+    ) -> anyhow::Result<()> {
+        cache::write(cache_file, Some(&self.fingerprint()), symbol, groups)?;
 
         Ok(())
     }
 }
 
-pub trait GitUrlProvider: FileProvider + HttpProvider {
+pub trait GitUrlProvider: FileProvider + HttpProvider + Send + Sync {
     fn symbol(&self) -> String;
 
+    /// How long the cached clone urls are served before they get refreshed.
+    /// `None` keeps them until the cache file is deleted.
+    fn cache_ttl(&self) -> Option<Duration> {
+        None
+    }
+
     fn collect_clone_urls(&self) -> anyhow::Result<Vec<CloneUrl>> {
-        let cache_file = &mut get_or_create_cache_file(self.name().to_string()).unwrap();
+        let cache_file = &mut get_or_create_cache_file(self.name().to_string())?;
 
         let cache_file_is_empty = file_is_empty(cache_file);
 
@@ -77,13 +255,241 @@ pub trait GitUrlProvider: FileProvider + HttpProvider {
                     "Collecting repo clone urls for '{}' from remote!",
                     self.name()
                 );
-                let clone_urls = self.request_from_remote(&self.symbol())?;
-                self.update_file(&clone_urls, cache_file)?;
-                clone_urls
+                self.refresh_cache()?
             }
-            false => self.load_from_file(cache_file, &self.symbol())?,
+            false => match self.load_cache(cache_file, &self.symbol()) {
+                Ok(content) if self.is_outdated(&content) => {
+                    println!(
+                        "Settings of '{}' changed, collecting repo clone urls from remote!",
+                        self.name()
+                    );
+                    // an outdated list still beats none at all
+                    self.refresh_cache().unwrap_or_else(|e| {
+                        eprintln!(
+                            "Could not refresh the cache of '{}', showing the outdated one: {}",
+                            self.name(),
+                            e
+                        );
+                        UrlGroup::flatten_unique(content.groups)
+                    })
+                }
+                Ok(content) => UrlGroup::flatten_unique(content.groups),
+                Err(e) => {
+                    println!(
+                        "Cache of '{}' is unreadable ({}), collecting repo clone urls from remote!",
+                        self.name(),
+                        e
+                    );
+                    self.refresh_cache()?
+                }
+            },
         };
 
         Ok(clone_urls)
     }
+
+    /// Serves whatever the cache holds without ever asking the remote, no
+    /// matter how old or outdated it is.
+    /// A missing cache counts as an empty one, nothing is written either.
+    fn collect_cached_clone_urls(&self) -> anyhow::Result<Vec<CloneUrl>> {
+        let content = read_cache_file(self.name()).unwrap_or_default();
+
+        if content.is_empty() {
+            println!("No repos of '{}' cached yet, skipping it!", self.name());
+            return Ok(vec![]);
+        }
+
+        let groups = cache::read(content.as_bytes(), &self.symbol())?.groups;
+
+        Ok(UrlGroup::flatten_unique(groups))
+    }
+
+    fn cache_is_stale(&self) -> bool {
+        match (self.cache_ttl(), cache_file_age(self.name())) {
+            (Some(ttl), Some(age)) => age > ttl,
+            _ => false,
+        }
+    }
+
+    /// Requests the clone urls from remote and swaps them into the cache in
+    /// one step, so a concurrent reader sees either the old or the new list.
+    fn refresh_cache(&self) -> anyhow::Result<Vec<CloneUrl>> {
+        let groups = self.request_groups_from_remote(&self.symbol())?;
+
+        replace_cache_file(self.name(), |cache_file| {
+            self.update_file(&groups, &self.symbol(), cache_file)
+        })?;
+
+        Ok(UrlGroup::flatten(groups))
+    }
+
+    /// Replaces the cached clone urls of one org or project with the current
+    /// ones and leaves all other cache entries untouched.
+    fn refresh_group(&self, group_name: &str) -> anyhow::Result<usize> {
+        // providers without groups and unknown groups fail before the cache
+        // is touched
+        let clone_urls = self
+            .request_group_from_remote(group_name, &self.symbol())
+            .ok_or_else(|| KloniError::GroupRefreshUnsupported(self.name().to_string()))??;
+        let refreshed = clone_urls.len();
+
+        // the cache is read again under the lock, another kloni might have
+        // replaced it in the meantime
+        let lock = lock_cache_file(self.name())?;
+        let cache_file = get_or_create_cache_file(self.name().to_string())?;
+
+        // a partial cache would never be completed by `collect_clone_urls`,
+        // and a single group collected with the current settings doesn't fix
+        // the others
+        let cached_groups = match file_is_empty(&cache_file) {
+            true => None,
+            false => match self.load_cache(&cache_file, &self.symbol()) {
+                Ok(content) if !self.is_outdated(&content) => Some(content.groups),
+                _ => None,
+            },
+        };
+
+        let Some(mut groups) = cached_groups else {
+            drop(lock);
+            println!(
+                "Cache of '{}' is missing or outdated, refreshing all of its repos",
+                self.name()
+            );
+            return Ok(self.refresh_cache()?.len());
+        };
+
+        match groups
+            .iter_mut()
+            .find(|group| group.name.eq_ignore_ascii_case(group_name))
+        {
+            Some(group) => group.clone_urls = clone_urls,
+            None => groups.push(UrlGroup::new(group_name, clone_urls)),
+        }
+
+        lock.replace(|cache_file| self.update_file(&groups, &self.symbol(), cache_file))?;
+
+        Ok(refreshed)
+    }
+}
+
+/// Everything of a provider that depends on its credentials or TLS settings:
+/// the actual implementation and what is needed to clone from it.
+pub struct Connection {
+    pub provider: Box<dyn GitUrlProvider>,
+    pub clone_auth: CloneAuth,
+    pub http: HttpSettings,
+}
+
+type Connect = Box<dyn Fn() -> anyhow::Result<Connection> + Send + Sync>;
+
+/// A provider as configured in `config.toml`. Wraps the actual implementation
+/// and replaces its generic name with the instance id, so that several
+/// instances of the same provider type don't share a cache file.
+///
+/// The implementation is connected on first use only. Serving the cache thus
+/// never runs token commands or credential helpers, and a provider whose
+/// token can't be resolved doesn't keep the others from working.
+pub struct ProviderInstance {
+    pub id: String,
+    pub symbol: String,
+    pub cache_ttl: Option<Duration>,
+    pub fingerprint: String,
+    connect: Connect,
+    connection: OnceLock<Result<Connection, String>>,
+}
+
+impl ProviderInstance {
+    pub fn new(
+        id: String,
+        connect: impl Fn() -> anyhow::Result<Connection> + Send + Sync + 'static,
+    ) -> ProviderInstance {
+        ProviderInstance {
+            id,
+            symbol: String::new(),
+            cache_ttl: None,
+            fingerprint: String::new(),
+            connect: Box::new(connect),
+            connection: OnceLock::new(),
+        }
+    }
+
+    pub fn with_symbol(mut self, symbol: String) -> ProviderInstance {
+        self.symbol = symbol;
+        self
+    }
+
+    pub fn with_cache_ttl(mut self, cache_ttl: Option<Duration>) -> ProviderInstance {
+        self.cache_ttl = cache_ttl;
+        self
+    }
+
+    pub fn with_fingerprint(mut self, fingerprint: String) -> ProviderInstance {
+        self.fingerprint = fingerprint;
+        self
+    }
+
+    /// Connects the provider on the first call. A failure is remembered, so
+    /// a token command isn't run again for every request.
+    pub fn connection(&self) -> anyhow::Result<&Connection> {
+        self.connection
+            .get_or_init(|| (self.connect)().map_err(|e| format!("{e:#}")))
+            .as_ref()
+            .map_err(|e| KloniError::ProviderUnavailable(self.id.to_owned(), e.to_owned()).into())
+    }
+}
+
+impl HttpProvider for ProviderInstance {
+    fn request_from_remote(&self, symbol: &str) -> anyhow::Result<Vec<CloneUrl>> {
+        self.connection()?.provider.request_from_remote(symbol)
+    }
+
+    /// Keeps the validators of all pages requested for the next refresh and
+    /// forgets those of pages that weren't requested anymore.
+    fn request_groups_from_remote(&self, symbol: &str) -> anyhow::Result<Vec<UrlGroup>> {
+        let connection = self.connection()?;
+        let groups = connection.provider.request_groups_from_remote(symbol)?;
+        connection.http.pages.save(true)?;
+
+        Ok(groups)
+    }
+
+    fn request_group_from_remote(
+        &self,
+        group: &str,
+        symbol: &str,
+    ) -> Option<anyhow::Result<Vec<CloneUrl>>> {
+        let connection = match self.connection() {
+            Ok(connection) => connection,
+            Err(e) => return Some(Err(e)),
+        };
+        let clone_urls = connection
+            .provider
+            .request_group_from_remote(group, symbol)?;
+
+        // the pages of all other groups are still needed
+        Some(clone_urls.and_then(|clone_urls| {
+            connection.http.pages.save(false)?;
+            Ok(clone_urls)
+        }))
+    }
+}
+
+impl FileProvider for ProviderInstance {
+    fn name(&self) -> &str {
+        &self.id
+    }
+
+    fn fingerprint(&self) -> String {
+        self.fingerprint.to_owned()
+    }
+}
+
+impl GitUrlProvider for ProviderInstance {
+    fn symbol(&self) -> String {
+        self.symbol.to_owned()
+    }
+
+    fn cache_ttl(&self) -> Option<Duration> {
+        self.cache_ttl
+    }
 }
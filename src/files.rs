@@ -2,13 +2,18 @@ pub mod config;
 
 use anyhow::Context;
 use directories::ProjectDirs;
+use fs4::fs_std::FileExt;
 use std::{
-    fs::{create_dir, File, OpenOptions},
+    collections::HashMap,
+    fs::{create_dir, metadata, read_dir, read_to_string, remove_file, rename, File, OpenOptions},
     io::Write,
-    path::PathBuf,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
 };
 use thiserror::Error;
 
+use self::config::Config;
+
 #[derive(Error, Debug)]
 pub enum FsError {
     #[error("no valid home directory path could be retrieved from the operating system")]
@@ -21,7 +26,7 @@ fn project_dirs() -> anyhow::Result<ProjectDirs> {
     Ok(dirs)
 }
 
-enum ConfigFileStatus {
+pub enum ConfigFileStatus {
     Created(PathBuf),
     Existing(PathBuf),
 }
@@ -35,16 +40,20 @@ pub fn get_or_create_config_file(default_content: &str) -> anyhow::Result<Config
 
     let config_toml_path = conf_dir_root.join("config.toml");
 
+    if config_toml_path.exists() {
+        return Ok(ConfigFileStatus::Existing(config_toml_path));
+    }
+
+    let lock = FileLock::acquire(&config_toml_path)?;
+
+    // another kloni may have been faster
     match config_toml_path.exists() {
         true => Ok(ConfigFileStatus::Existing(config_toml_path)),
         false => {
-            let mut conf_file = OpenOptions::new()
-                .read(true)
-                .write(true)
-                .create(true) // creates en empty file, if not exist
-                .open(&config_toml_path)?;
-
-            write!(conf_file, "{}", default_content)?;
+            lock.replace(|conf_file| {
+                write!(conf_file, "{}", default_content)?;
+                Ok(())
+            })?;
 
             Ok(ConfigFileStatus::Created(config_toml_path))
         }
@@ -85,15 +94,322 @@ pub fn get_or_create_cache_file(file_name: String) -> anyhow::Result<File> {
                 .read(true)
                 .write(true)
                 .create(true) // creates en empty file, if not exist
+                .truncate(false)
                 .open(cache_file_path)
                 .context("clould not open cache '{cache_file_path}'")?;
             Ok(cache_file)
         }
     }
 }
+
+/// Content of the cache file, `None` if there is none (yet).
+pub fn read_cache_file(file_name: &str) -> Option<String> {
+    let cache_file_path = project_dirs().ok()?.cache_dir().join(file_name);
+
+    read_to_string(cache_file_path).ok()
+}
+
+pub fn cache_file_modified(file_name: &str) -> Option<SystemTime> {
+    let cache_file_path = project_dirs().ok()?.cache_dir().join(file_name);
+
+    metadata(cache_file_path).ok()?.modified().ok()
+}
+
+/// Time since the cache file was last written, `None` if there is none yet.
+pub fn cache_file_age(file_name: &str) -> Option<Duration> {
+    let modified = cache_file_modified(file_name)?;
+
+    // a modification time in the future counts as just written
+    Some(
+        SystemTime::now()
+            .duration_since(modified)
+            .unwrap_or_default(),
+    )
+}
+
+/// An exclusive advisory lock on `<file>.lock`, released when dropped. Keeps
+/// several kloni processes, e.g. a cron refresh and an interactive run, from
+/// writing the same file at once. Readers don't need it, as files are only
+/// ever replaced as a whole.
+pub struct FileLock {
+    path: PathBuf,
+    _lock_file: File,
+}
+
+impl FileLock {
+    pub fn acquire(path: &Path) -> anyhow::Result<FileLock> {
+        let lock_file = Self::open_lock_file(path)?;
+
+        lock_file
+            .lock_exclusive()
+            .context(format!("could not lock '{}'", path.display()))?;
+
+        Ok(FileLock {
+            path: path.to_owned(),
+            _lock_file: lock_file,
+        })
+    }
+
+    /// `None` if somebody else holds the lock right now.
+    pub fn try_acquire(path: &Path) -> anyhow::Result<Option<FileLock>> {
+        let lock_file = Self::open_lock_file(path)?;
+
+        let is_locked = lock_file
+            .try_lock_exclusive()
+            .context(format!("could not lock '{}'", path.display()))?;
+
+        Ok(is_locked.then(|| FileLock {
+            path: path.to_owned(),
+            _lock_file: lock_file,
+        }))
+    }
+
+    fn open_lock_file(path: &Path) -> anyhow::Result<File> {
+        let mut lock_file_name = path.as_os_str().to_owned();
+        lock_file_name.push(".lock");
+        let lock_file_path = PathBuf::from(lock_file_name);
+
+        OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&lock_file_path)
+            .context(format!("could not open '{}'", lock_file_path.display()))
+    }
+
+    /// Writes the new content next to the locked file and renames it into
+    /// place, so a crash never leaves a half written file behind.
+    pub fn replace(
+        &self,
+        write_content: impl FnOnce(&mut File) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        let mut swap_file_name = self.path.as_os_str().to_owned();
+        swap_file_name.push(".swap");
+        let swap_file_path = PathBuf::from(swap_file_name);
+
+        let mut swap_file = File::create(&swap_file_path)
+            .context(format!("could not create '{}'", swap_file_path.display()))?;
+
+        write_content(&mut swap_file)?;
+        swap_file.sync_all()?;
+
+        rename(&swap_file_path, &self.path)
+            .context(format!("could not replace '{}'", self.path.display()))?;
+
+        Ok(())
+    }
+}
+
+fn cache_file_path(file_name: &str) -> anyhow::Result<PathBuf> {
+    let cache_dir_root = project_dirs()?.cache_dir().to_owned();
+
+    if !cache_dir_root.exists() {
+        create_dir(&cache_dir_root).context("could not create '{cache_dir_root}'")?;
+    }
+
+    Ok(cache_dir_root.join(file_name))
+}
+
+pub fn lock_cache_file(file_name: &str) -> anyhow::Result<FileLock> {
+    FileLock::acquire(&cache_file_path(file_name)?)
+}
+
+pub fn try_lock_cache_file(file_name: &str) -> anyhow::Result<Option<FileLock>> {
+    FileLock::try_acquire(&cache_file_path(file_name)?)
+}
+
+/// Writes a new cache next to the current one and renames it into place.
+pub fn replace_cache_file(
+    file_name: &str,
+    write_content: impl FnOnce(&mut File) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    lock_cache_file(file_name)?.replace(write_content)
+}
+
+/// Moves caches written before provider ids existed (named after the provider
+/// type) to the id of the only configured provider of that type and removes
+/// leftovers kloni can tell are its own. Caches of providers that aren't
+/// configured right now are kept, they might only be commented out.
+pub fn migrate_cache_files(config: &Config) -> anyhow::Result<()> {
+    let cache_dir_root = project_dirs()?.cache_dir().to_owned();
+
+    if !cache_dir_root.exists() {
+        return Ok(());
+    }
+
+    let instances = config
+        .providers
+        .iter()
+        .map(|provider| (provider.provider.name(), provider.id()))
+        .collect::<Vec<(&str, String)>>();
+
+    tidy_cache_dir(&cache_dir_root, &instances)
+}
+
+fn tidy_cache_dir(cache_dir: &Path, instances: &[(&str, String)]) -> anyhow::Result<()> {
+    let mut ids_by_type: HashMap<&str, Vec<&String>> = HashMap::new();
+
+    for (type_name, id) in instances {
+        ids_by_type.entry(type_name).or_default().push(id);
+    }
+
+    // a legacy cache of a type configured several times is left alone, it's
+    // unclear which instance it belongs to
+    for (type_name, ids) in &ids_by_type {
+        let legacy_cache = cache_dir.join(type_name);
+        let [id] = ids.as_slice() else {
+            continue;
+        };
+        let id_cache = cache_dir.join(id);
+
+        if legacy_cache.is_file() && !id_cache.exists() {
+            rename(&legacy_cache, &id_cache).context(format!(
+                "could not migrate cache '{}'",
+                legacy_cache.display()
+            ))?;
+        }
+    }
+
+    for entry in read_dir(cache_dir)?.flatten() {
+        let file_name = entry.file_name().to_string_lossy().to_string();
+
+        // the lock is held until the leftover is gone
+        let (is_leftover, _lock) = match file_name.rsplit_once('.') {
+            // a write that was interrupted, unless another kloni is writing
+            Some((cache_name, "swap")) if is_cache_name(cache_name, instances) => {
+                let lock = FileLock::try_acquire(&cache_dir.join(cache_name))?;
+                (lock.is_some(), lock)
+            }
+            // only Github keeps its pages
+            Some((id, "pages")) => {
+                let is_leftover = instances
+                    .iter()
+                    .any(|(type_name, known_id)| known_id == id && *type_name != "github");
+                (is_leftover, None)
+            }
+            _ => (false, None),
+        };
+
+        if is_leftover && entry.path().is_file() {
+            remove_file(entry.path()).context(format!(
+                "could not remove stale cache '{}'",
+                entry.path().display()
+            ))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether kloni names caches like this: after a configured id, a derived id
+/// (`<type>-<16 hex digits>`) or, before ids existed, the provider type.
+fn is_cache_name(name: &str, instances: &[(&str, String)]) -> bool {
+    let is_derived_id = name.rsplit_once('-').is_some_and(|(type_name, hash)| {
+        !type_name.is_empty() && hash.len() == 16 && hash.chars().all(|c| c.is_ascii_hexdigit())
+    });
+
+    is_derived_id
+        || instances
+            .iter()
+            .any(|(type_name, id)| name == *type_name || name == id)
+}
+
 pub fn file_is_empty(file: &File) -> bool {
     match file.metadata() {
         Ok(md) => md.len() == 0,
         _ => false,
     }
 }
+
+#[cfg(test)]
+mod tests {
+
+    use std::fs::{create_dir_all, read_to_string, remove_dir_all, write};
+
+    use crate::files::{tidy_cache_dir, FileLock};
+
+    #[test]
+    pub fn should_migrate_legacy_cache_and_remove_own_leftovers_only() {
+        let cache_dir = std::env::temp_dir().join(format!("kloni-cache-{}", std::process::id()));
+        let _ = remove_dir_all(&cache_dir);
+        create_dir_all(&cache_dir).unwrap();
+
+        write(cache_dir.join("github"), "git@ghes:org/repo.git").unwrap();
+        write(cache_dir.join("bitbucket"), "ssh://git@bb/prj/repo.git").unwrap();
+        write(
+            cache_dir.join("gitlab-0123456789abcdef"),
+            "git@gl:group/repo.git",
+        )
+        .unwrap();
+        write(
+            cache_dir.join("gitlab-0123456789abcdef.swap"),
+            "git@gl:group/",
+        )
+        .unwrap();
+        write(cache_dir.join("github-prod.pages"), "{}").unwrap();
+        write(cache_dir.join("bitbucket-1.pages"), "{}").unwrap();
+        write(cache_dir.join("notes.swap"), "not kloni's").unwrap();
+
+        let instances = [
+            ("github", "github-prod".to_string()),
+            ("bitbucket", "bitbucket-1".to_string()),
+            ("bitbucket", "bitbucket-2".to_string()),
+        ];
+
+        tidy_cache_dir(&cache_dir, &instances).unwrap();
+
+        let migrated = read_to_string(cache_dir.join("github-prod")).unwrap();
+        let exists = |file_name: &str| cache_dir.join(file_name).exists();
+        let github_legacy_exists = exists("github");
+        let bitbucket_legacy_exists = exists("bitbucket");
+        let unconfigured_exists = exists("gitlab-0123456789abcdef");
+        let interrupted_write_exists = exists("gitlab-0123456789abcdef.swap");
+        let github_pages_exist = exists("github-prod.pages");
+        let bitbucket_pages_exist = exists("bitbucket-1.pages");
+        let foreign_file_exists = exists("notes.swap");
+
+        remove_dir_all(&cache_dir).unwrap();
+
+        assert_eq!(migrated, "git@ghes:org/repo.git");
+        assert!(!github_legacy_exists);
+        // ambiguous which instance it belonged to, so it's kept
+        assert!(bitbucket_legacy_exists);
+        // the provider might only be commented out
+        assert!(unconfigured_exists);
+        assert!(!interrupted_write_exists);
+        assert!(github_pages_exist);
+        assert!(!bitbucket_pages_exist);
+        assert!(foreign_file_exists);
+    }
+
+    #[test]
+    pub fn should_replace_file_only_while_holding_the_lock() {
+        let dir = std::env::temp_dir().join(format!("kloni-lock-{}", std::process::id()));
+        let _ = remove_dir_all(&dir);
+        create_dir_all(&dir).unwrap();
+
+        let cache = dir.join("github-prod");
+        write(&cache, "a much longer list of old clone urls").unwrap();
+
+        let lock = FileLock::acquire(&cache).unwrap();
+        let is_busy = FileLock::try_acquire(&cache).unwrap().is_none();
+
+        lock.replace(|file| {
+            std::io::Write::write_all(file, b"new urls")?;
+            Ok(())
+        })
+        .unwrap();
+        drop(lock);
+
+        let is_free = FileLock::try_acquire(&cache).unwrap().is_some();
+        let replaced = read_to_string(&cache).unwrap();
+        let swap_exists = dir.join("github-prod.swap").exists();
+
+        remove_dir_all(&dir).unwrap();
+
+        assert!(is_busy);
+        assert!(is_free);
+        assert_eq!(replaced, "new urls");
+        assert!(!swap_exists);
+    }
+}
@@ -0,0 +1,253 @@
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+
+use crate::core::{CloneUrl, FileProvider, GitUrlProvider, HttpProblem, HttpProvider, RepoMeta};
+use crate::files::config::Protocol;
+use crate::http::HttpSettings;
+
+#[derive(Debug, Deserialize)]
+pub struct Project {
+    pub id: String,
+    pub description: Option<String>,
+    pub state: Option<String>,
+}
+
+pub struct Gerrit {
+    pub username: String,
+    pub token: String,
+    pub projects_url: String,
+    pub ssh_host: String,
+    pub ssh_port: u16,
+    pub symbol: Option<String>,
+    pub protocol: Protocol,
+    pub http: HttpSettings,
+}
+
+pub const PROJECTS_PATH: &str = "/a/projects/?d";
+
+pub const DEFAULT_SSH_PORT: u16 = 29418;
+
+/// Gerrit prefixes every JSON response with this line to prevent XSSI.
+const XSSI_PREFIX: &str = ")]}'";
+
+impl Gerrit {
+    pub fn new(
+        username: String,
+        token: String,
+        projects_url: String,
+        ssh_host: String,
+        ssh_port: u16,
+        symbol: Option<String>,
+    ) -> Gerrit {
+        Gerrit {
+            username,
+            token,
+            projects_url,
+            ssh_host,
+            ssh_port,
+            symbol,
+            protocol: Protocol::ssh,
+            http: HttpSettings::default(),
+        }
+    }
+
+    pub fn with_protocol(mut self, protocol: Protocol) -> Gerrit {
+        self.protocol = protocol;
+        self
+    }
+
+    pub fn with_http(mut self, http: HttpSettings) -> Gerrit {
+        self.http = http;
+        self
+    }
+
+    pub fn fetch_clone_urls(&self, symbol: &str) -> anyhow::Result<Vec<CloneUrl>> {
+        let projects = self.get_all_projects()?;
+
+        let git_urls = projects
+            .into_iter()
+            .filter(|(_, project)| project.state.as_deref() != Some("HIDDEN"))
+            .map(|(name, project)| {
+                let meta = RepoMeta {
+                    name: name.rsplit('/').next().map(str::to_string),
+                    full_name: Some(name.to_owned()),
+                    description: project.description,
+                    archived: Some(project.state.as_deref() == Some("READ_ONLY")),
+                    ..RepoMeta::default()
+                };
+
+                CloneUrl::new(self.clone_url_for(&name), symbol.to_string()).with_meta(meta)
+            })
+            .collect();
+
+        Ok(git_urls)
+    }
+
+    fn clone_url_for(&self, project_name: &str) -> String {
+        match self.protocol {
+            Protocol::ssh => format!(
+                "ssh://{}@{}:{}/{}",
+                self.username, self.ssh_host, self.ssh_port, project_name
+            ),
+            // authenticated git over http is served below '/a/' like the api
+            Protocol::https => {
+                let root_url = self
+                    .projects_url
+                    .strip_suffix(PROJECTS_PATH)
+                    .unwrap_or(&self.projects_url);
+                format!("{}/a/{}", root_url, project_name)
+            }
+        }
+    }
+
+    /// Returns all projects keyed (and therefore sorted) by their name.
+    pub fn get_all_projects(&self) -> anyhow::Result<BTreeMap<String, Project>> {
+        let url = &self.projects_url;
+
+        let response = self
+            .http
+            .get(url)?
+            .basic_auth(&self.username, Some(&self.token))
+            .send()
+            .map_err(|e| HttpProblem::RequestFailed(url.to_string(), e.to_string()))?;
+
+        if !response.is_success() {
+            return Err(HttpProblem::RequestFailed(
+                url.to_string(),
+                format!("status: {}", response.status()),
+            )
+            .into());
+        }
+
+        let body = response
+            .text()
+            .map_err(|e| HttpProblem::RequestFailed(url.to_string(), e.to_string()))?;
+
+        Self::parse_projects(&body)
+            .map_err(|e| HttpProblem::DeserializationFailed(url.to_string(), e.to_string()).into())
+    }
+
+    fn parse_projects(body: &str) -> serde_json::Result<BTreeMap<String, Project>> {
+        let json = body.trim_start().strip_prefix(XSSI_PREFIX).unwrap_or(body);
+
+        serde_json::from_str::<BTreeMap<String, Project>>(json)
+    }
+
+    /// Extracts the host name from an url like `https://review.acme.org:8443/gerrit`.
+    pub fn host_of(base_url: &str) -> &str {
+        let without_scheme = base_url
+            .split_once("://")
+            .map(|(_, rest)| rest)
+            .unwrap_or(base_url);
+
+        let authority = without_scheme.split('/').next().unwrap_or(without_scheme);
+        let host = authority.rsplit('@').next().unwrap_or(authority);
+
+        host.split(':').next().unwrap_or(host)
+    }
+}
+
+impl HttpProvider for Gerrit {
+    fn request_from_remote(&self, symbol: &str) -> anyhow::Result<Vec<CloneUrl>> {
+        let result = self.fetch_clone_urls(symbol)?;
+        Ok(result)
+    }
+}
+
+impl FileProvider for Gerrit {
+    fn name(&self) -> &str {
+        "gerrit"
+    }
+}
+
+impl GitUrlProvider for Gerrit {
+    fn symbol(&self) -> String {
+        self.symbol.to_owned().unwrap_or("".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::core::{CloneUrl, RepoMeta};
+    use crate::gerrit::{Gerrit, DEFAULT_SSH_PORT};
+    use httpmock::prelude::*;
+
+    #[test]
+    pub fn should_extract_host_from_base_url() {
+        assert_eq!(
+            Gerrit::host_of("https://review.acme.org"),
+            "review.acme.org"
+        );
+        assert_eq!(
+            Gerrit::host_of("https://review.acme.org:8443/gerrit"),
+            "review.acme.org"
+        );
+    }
+
+    #[test]
+    pub fn should_fetch_ssh_urls_and_strip_xssi_prefix() {
+        let server = MockServer::start();
+        let address = server.address().to_string();
+        let projects_url = format!("http://{address}/a/projects/?d");
+
+        let projects_mock = server.mock(|when, then| {
+            when.method("GET")
+                // base64("jdoe:s3cr3t")
+                .header("Authorization", "Basic amRvZTpzM2NyM3Q=")
+                .path("/a/projects/")
+                .query_param_exists("d");
+            then.status(200)
+                .header("content-type", "application/json; charset=utf-8")
+                .body(
+                    r#")]}'
+{
+  "platform/build": { "id": "platform%2Fbuild", "description": "Build system", "state": "READ_ONLY" },
+  "All-Projects": { "id": "All-Projects", "description": "Access inherited by all other projects.", "state": "ACTIVE" },
+  "secret/stuff": { "id": "secret%2Fstuff", "state": "HIDDEN" }
+}"#,
+                );
+        });
+
+        let gerrit = Gerrit::new(
+            "jdoe".to_string(),
+            "s3cr3t".to_string(),
+            projects_url,
+            "review.acme.org".to_string(),
+            DEFAULT_SSH_PORT,
+            None,
+        );
+        let cloneable_urls = gerrit.fetch_clone_urls("GR").unwrap();
+
+        projects_mock.assert();
+
+        assert_eq!(
+            cloneable_urls,
+            vec![
+                CloneUrl::new(
+                    "ssh://jdoe@review.acme.org:29418/All-Projects".to_string(),
+                    "GR".to_string()
+                )
+                .with_meta(RepoMeta {
+                    name: Some("All-Projects".to_string()),
+                    full_name: Some("All-Projects".to_string()),
+                    description: Some("Access inherited by all other projects.".to_string()),
+                    archived: Some(false),
+                    ..RepoMeta::default()
+                }),
+                CloneUrl::new(
+                    "ssh://jdoe@review.acme.org:29418/platform/build".to_string(),
+                    "GR".to_string()
+                )
+                .with_meta(RepoMeta {
+                    name: Some("build".to_string()),
+                    full_name: Some("platform/build".to_string()),
+                    description: Some("Build system".to_string()),
+                    archived: Some(true),
+                    ..RepoMeta::default()
+                }),
+            ]
+        );
+    }
+}
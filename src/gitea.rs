@@ -0,0 +1,346 @@
+use std::collections::HashSet;
+
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::core::{CloneUrl, FileProvider, GitUrlProvider, HttpProblem, HttpProvider, RepoMeta};
+use crate::files::config::Protocol;
+use crate::http::HttpSettings;
+
+#[derive(Debug, Deserialize)]
+pub struct Repo {
+    pub name: String,
+    pub full_name: String,
+    pub description: Option<String>,
+    pub ssh_url: String,
+    pub clone_url: Option<String>,
+    pub default_branch: Option<String>,
+    pub archived: Option<bool>,
+    pub fork: Option<bool>,
+    pub created_at: Option<String>,
+    pub private: Option<bool>,
+    pub internal: Option<bool>,
+    /// In kilobytes
+    pub size: Option<u64>,
+    #[serde(default)]
+    pub topics: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Organization {
+    pub username: String,
+}
+
+pub struct Gitea {
+    pub token: String,
+    pub api_url: String,
+    pub symbol: Option<String>,
+    pub protocol: Protocol,
+    pub http: HttpSettings,
+}
+
+pub const API_PATH: &str = "/api/v1";
+
+const PAGE_LIMIT: usize = 50;
+
+impl Gitea {
+    pub fn new(token: String, api_url: String, symbol: Option<String>) -> Gitea {
+        Gitea {
+            token,
+            api_url,
+            symbol,
+            protocol: Protocol::ssh,
+            http: HttpSettings::default(),
+        }
+    }
+
+    pub fn with_protocol(mut self, protocol: Protocol) -> Gitea {
+        self.protocol = protocol;
+        self
+    }
+
+    pub fn with_http(mut self, http: HttpSettings) -> Gitea {
+        self.http = http;
+        self
+    }
+
+    pub fn fetch_clone_urls(&self, symbol: &str) -> anyhow::Result<Vec<CloneUrl>> {
+        let user_repos_url = format!("{}/user/repos", &self.api_url);
+        let mut repos = Self::get_all_pages::<Repo>(&self.http, &self.token, &user_repos_url)?;
+
+        let orgs_url = format!("{}/user/orgs", &self.api_url);
+        let orgs = Self::get_all_pages::<Organization>(&self.http, &self.token, &orgs_url)?;
+
+        for Organization { username } in orgs {
+            let org_repos_url = format!("{}/orgs/{}/repos", &self.api_url, username);
+            repos.extend(Self::get_all_pages::<Repo>(
+                &self.http,
+                &self.token,
+                &org_repos_url,
+            )?);
+        }
+
+        // org repos the user is a member of are already part of `/user/repos`
+        let mut seen_names: HashSet<String> = HashSet::new();
+        let git_urls = repos
+            .into_iter()
+            .filter(|repo| seen_names.insert(repo.full_name.clone()))
+            .map(|repo| self.clone_url_of(repo, symbol))
+            .collect();
+
+        Ok(git_urls)
+    }
+
+    fn clone_url_of(&self, repo: Repo, symbol: &str) -> CloneUrl {
+        let url = match self.protocol {
+            Protocol::ssh => repo.ssh_url,
+            Protocol::https => repo.clone_url.unwrap_or(repo.ssh_url),
+        };
+
+        let visibility = match (repo.internal, repo.private) {
+            (Some(true), _) => Some("internal"),
+            (_, Some(true)) => Some("private"),
+            (_, Some(false)) => Some("public"),
+            _ => None,
+        };
+
+        CloneUrl::new(url, symbol.to_string()).with_meta(RepoMeta {
+            name: Some(repo.name),
+            full_name: Some(repo.full_name),
+            description: repo.description,
+            default_branch: repo.default_branch,
+            archived: repo.archived,
+            fork: repo.fork,
+            created_at: repo.created_at,
+            visibility: visibility.map(str::to_string),
+            size: repo.size,
+            tags: repo.topics,
+            ..RepoMeta::default()
+        })
+    }
+
+    pub fn get_all_pages<T: DeserializeOwned>(
+        http: &HttpSettings,
+        token: &str,
+        url: &str,
+    ) -> anyhow::Result<Vec<T>> {
+        let mut results = vec![];
+        let mut page = 1;
+
+        loop {
+            let response = http
+                .get(url)?
+                .header("Authorization", format!("token {token}"))
+                .param("page", page)
+                .param("limit", PAGE_LIMIT)
+                .send()
+                .map_err(|e| HttpProblem::RequestFailed(url.to_string(), e.to_string()))?;
+
+            if !response.is_success() {
+                return Err(HttpProblem::RequestFailed(
+                    url.to_string(),
+                    format!("status: {}", response.status()),
+                )
+                .into());
+            }
+
+            let deserialized_result = response
+                .json::<Vec<T>>()
+                .map_err(|e| HttpProblem::DeserializationFailed(url.to_string(), e.to_string()))?;
+
+            // the server may cap `limit` below the requested value, so only an
+            // empty page reliably marks the end
+            if deserialized_result.is_empty() {
+                break;
+            }
+
+            results.extend(deserialized_result);
+            page += 1;
+        }
+
+        Ok(results)
+    }
+}
+
+impl HttpProvider for Gitea {
+    fn request_from_remote(&self, symbol: &str) -> anyhow::Result<Vec<CloneUrl>> {
+        let result = self.fetch_clone_urls(symbol)?;
+        Ok(result)
+    }
+}
+
+impl FileProvider for Gitea {
+    fn name(&self) -> &str {
+        "gitea"
+    }
+}
+
+impl GitUrlProvider for Gitea {
+    fn symbol(&self) -> String {
+        self.symbol.to_owned().unwrap_or("".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::core::{CloneUrl, RepoMeta};
+    use crate::gitea::Gitea;
+    use httpmock::prelude::*;
+    use serde_json::json;
+
+    #[test]
+    pub fn should_fetch_user_and_org_repos_without_duplicates() {
+        let server = MockServer::start();
+        let address = server.address().to_string();
+        let api_url = format!("http://{address}/api/v1");
+
+        let user_repos_page_1_mock = server.mock(|when, then| {
+            when.method("GET")
+                .header("Authorization", "token s3cr3t")
+                .path("/api/v1/user/repos")
+                .query_param("page", "1");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(
+                    json!([
+                        {
+                            "name": "dotfiles",
+                            "full_name": "jdoe/dotfiles",
+                            "description": "",
+                            "ssh_url": "git@localhost:jdoe/dotfiles.git"
+                        },
+                        {
+                            "name": "infra",
+                            "full_name": "tooling/infra",
+                            "description": null,
+                            "ssh_url": "git@localhost:tooling/infra.git"
+                        }
+                    ])
+                    .to_string(),
+                );
+        });
+
+        let user_repos_page_2_mock = server.mock(|when, then| {
+            when.method("GET")
+                .path("/api/v1/user/repos")
+                .query_param("page", "2");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body("[]");
+        });
+
+        let orgs_page_1_mock = server.mock(|when, then| {
+            when.method("GET")
+                .path("/api/v1/user/orgs")
+                .query_param("page", "1");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(json!([{ "username": "tooling" }]).to_string());
+        });
+
+        let orgs_page_2_mock = server.mock(|when, then| {
+            when.method("GET")
+                .path("/api/v1/user/orgs")
+                .query_param("page", "2");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body("[]");
+        });
+
+        let org_repos_page_1_mock = server.mock(|when, then| {
+            when.method("GET")
+                .path("/api/v1/orgs/tooling/repos")
+                .query_param("page", "1")
+                .query_param("limit", "50");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(
+                    json!([
+                        {
+                            "name": "infra",
+                            "full_name": "tooling/infra",
+                            "description": null,
+                            "ssh_url": "git@localhost:tooling/infra.git"
+                        },
+                        {
+                            "name": "runner",
+                            "full_name": "tooling/runner",
+                            "description": "CI runner",
+                            "ssh_url": "git@localhost:tooling/runner.git",
+                            "default_branch": "main",
+                            "archived": true,
+                            "fork": false,
+                            "created_at": "2022-11-03T10:00:00Z",
+                            "private": true,
+                            "internal": false,
+                            "size": 312,
+                            "topics": ["ci"]
+                        }
+                    ])
+                    .to_string(),
+                );
+        });
+
+        let org_repos_page_2_mock = server.mock(|when, then| {
+            when.method("GET")
+                .path("/api/v1/orgs/tooling/repos")
+                .query_param("page", "2");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body("[]");
+        });
+
+        let gitea = Gitea::new("s3cr3t".to_string(), api_url, Some("GT".to_string()));
+        let cloneable_urls = gitea.fetch_clone_urls("GT").unwrap();
+
+        user_repos_page_1_mock.assert();
+        user_repos_page_2_mock.assert();
+        orgs_page_1_mock.assert();
+        orgs_page_2_mock.assert();
+        org_repos_page_1_mock.assert();
+        org_repos_page_2_mock.assert();
+
+        assert_eq!(
+            cloneable_urls,
+            vec![
+                CloneUrl::new(
+                    "git@localhost:jdoe/dotfiles.git".to_string(),
+                    "GT".to_string()
+                )
+                .with_meta(RepoMeta {
+                    name: Some("dotfiles".to_string()),
+                    full_name: Some("jdoe/dotfiles".to_string()),
+                    description: Some("".to_string()),
+                    ..RepoMeta::default()
+                }),
+                CloneUrl::new(
+                    "git@localhost:tooling/infra.git".to_string(),
+                    "GT".to_string()
+                )
+                .with_meta(RepoMeta {
+                    name: Some("infra".to_string()),
+                    full_name: Some("tooling/infra".to_string()),
+                    ..RepoMeta::default()
+                }),
+                CloneUrl::new(
+                    "git@localhost:tooling/runner.git".to_string(),
+                    "GT".to_string()
+                )
+                .with_meta(RepoMeta {
+                    name: Some("runner".to_string()),
+                    full_name: Some("tooling/runner".to_string()),
+                    description: Some("CI runner".to_string()),
+                    default_branch: Some("main".to_string()),
+                    archived: Some(true),
+                    fork: Some(false),
+                    created_at: Some("2022-11-03T10:00:00Z".to_string()),
+                    visibility: Some("private".to_string()),
+                    size: Some(312),
+                    tags: vec!["ci".to_string()],
+                    ..RepoMeta::default()
+                }),
+            ]
+        );
+    }
+}
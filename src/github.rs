@@ -1,10 +1,15 @@
+use std::collections::HashSet;
+
 use attohttpc::header::HeaderMap;
-use attohttpc::{Method, RequestBuilder};
 use lazy_static::lazy_static;
 use regex::Regex;
-use serde::Deserialize;
+use serde::{de::DeserializeOwned, Deserialize};
 
-use crate::core::{CloneUrl, FileProvider, GitUrlProvider, HttpProblem, HttpProvider};
+use crate::core::{
+    CloneUrl, FileProvider, GitUrlProvider, HttpProvider, KloniError, RepoMeta, UrlGroup,
+};
+use crate::files::config::{GithubSource, Protocol};
+use crate::http::HttpSettings;
 
 #[derive(Debug, Deserialize)]
 pub struct Repo {
@@ -12,90 +17,274 @@ pub struct Repo {
     pub full_name: String,
     pub description: Option<String>,
     pub ssh_url: String,
+    pub clone_url: Option<String>,
+    pub default_branch: Option<String>,
+    pub archived: Option<bool>,
+    pub fork: Option<bool>,
+    pub created_at: Option<String>,
+    pub pushed_at: Option<String>,
+    pub visibility: Option<String>,
+    pub private: Option<bool>,
+    pub size: Option<u64>,
+    #[serde(default)]
+    pub topics: Vec<String>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct Organization {
+    #[serde(default)]
+    pub login: String,
     pub repos_url: String,
 }
 
+/// A Github user or org, as far as it tells which one it is.
+#[derive(Debug, Deserialize)]
+pub struct Account {
+    #[serde(rename = "type")]
+    pub kind: String,
+}
+
 #[derive(Debug)]
 pub struct OrganizationRepoUrl(pub String);
 
 pub struct Github {
     pub token: String,
-    pub orgs_url: String,
+    pub api_url: String,
     pub symbol: Option<String>,
+    pub sources: Vec<GithubSource>,
+    pub extra_orgs: Vec<String>,
+    pub extra_users: Vec<String>,
+    pub protocol: Protocol,
+    pub http: HttpSettings,
 }
 
 lazy_static! {
     static ref NEXT_LINK_REGEX: Regex = Regex::new(r#"(?i)(http\S*\d+)>;\s+(rel="next")"#).unwrap();
 }
 
-pub const USER_ORGS_PATH: &str = "/api/v3/user/orgs";
+pub const API_PATH: &str = "/api/v3";
+
+pub const USER_ORGS_PATH: &str = "/user/orgs";
+
+pub const USER_REPOS_PATH: &str = "/user/repos?affiliation=owner,collaborator,organization_member";
+
+pub const USER_STARRED_PATH: &str = "/user/starred";
+
+pub const PUBLIC_API_URL: &str = "https://api.github.com";
 
 impl Github {
-    pub fn new(token: String, orgs_url: String, symbol: Option<String>) -> Github {
+    pub fn new(token: String, api_url: String, symbol: Option<String>) -> Github {
         Github {
             token,
-            orgs_url,
+            api_url,
             symbol,
+            sources: vec![GithubSource::orgs],
+            extra_orgs: vec![],
+            extra_users: vec![],
+            protocol: Protocol::ssh,
+            http: HttpSettings::default(),
+        }
+    }
+
+    pub fn with_protocol(mut self, protocol: Protocol) -> Github {
+        self.protocol = protocol;
+        self
+    }
+
+    pub fn with_http(mut self, http: HttpSettings) -> Github {
+        self.http = http;
+        self
+    }
+
+    pub fn with_sources(
+        mut self,
+        sources: Vec<GithubSource>,
+        extra_orgs: Vec<String>,
+        extra_users: Vec<String>,
+    ) -> Github {
+        self.sources = sources;
+        self.extra_orgs = extra_orgs;
+        self.extra_users = extra_users;
+        self
+    }
+
+    /// github.com serves its api from a separate host while Github Enterprise
+    /// Server serves it below `/api/v3` of the instance itself.
+    pub fn default_api_url(base_url: &str) -> String {
+        let base_url = base_url.trim_end_matches('/');
+
+        match base_url {
+            "https://github.com" | "https://www.github.com" | PUBLIC_API_URL => {
+                PUBLIC_API_URL.to_string()
+            }
+            _ => format!("{}{}", base_url, API_PATH),
         }
     }
 
     pub fn fetch_clone_urls(&self, symbol: &str) -> anyhow::Result<Vec<CloneUrl>> {
-        let orgs = Self::get_all_organizations(&self.token, &self.orgs_url)?;
+        Ok(UrlGroup::flatten(self.fetch_grouped_clone_urls(symbol)?))
+    }
 
-        let repo_urls: Vec<OrganizationRepoUrl> = orgs
-            .iter()
-            .map(|org| OrganizationRepoUrl(org.repos_url.to_string()))
+    /// Groups the clone urls by org or user. Repos of the `user_repos` and
+    /// `starred` sources end up in an unnamed group.
+    pub fn fetch_grouped_clone_urls(&self, symbol: &str) -> anyhow::Result<Vec<UrlGroup>> {
+        let mut repo_urls: Vec<(String, OrganizationRepoUrl)> = vec![];
+
+        for source in &self.sources {
+            match source {
+                GithubSource::orgs => {
+                    let orgs_url = format!("{}{}", &self.api_url, USER_ORGS_PATH);
+                    let orgs = Self::get_all_organizations(&self.http, &self.token, &orgs_url)?;
+
+                    repo_urls.extend(orgs.iter().map(|org| {
+                        (
+                            org.login.to_string(),
+                            OrganizationRepoUrl(org.repos_url.to_string()),
+                        )
+                    }));
+                }
+                GithubSource::user_repos => repo_urls.push((
+                    "".to_string(),
+                    OrganizationRepoUrl(format!("{}{}", &self.api_url, USER_REPOS_PATH)),
+                )),
+                GithubSource::starred => repo_urls.push((
+                    "".to_string(),
+                    OrganizationRepoUrl(format!("{}{}", &self.api_url, USER_STARRED_PATH)),
+                )),
+            }
+        }
+
+        repo_urls.extend(
+            self.extra_orgs
+                .iter()
+                .chain(self.extra_users.iter())
+                .map(|account| (account.to_string(), self.account_repos_url(account))),
+        );
+
+        let mut groups: Vec<UrlGroup> = vec![];
+        let mut seen_names: HashSet<String> = HashSet::new();
+
+        for (group_name, OrganizationRepoUrl(url)) in repo_urls {
+            let git_repos = Self::get_all_repos(&self.http, &self.token, url.as_str())?;
+
+            // the same repo may be reachable through several sources
+            let clone_urls = git_repos
+                .into_iter()
+                .filter(|repo| seen_names.insert(repo.full_name.to_owned()))
+                .map(|repo| self.clone_url_of(repo, symbol))
+                .collect();
+
+            groups.push(UrlGroup::new(&group_name, clone_urls));
+        }
+
+        Ok(groups)
+    }
+
+    /// Lists the repos of a single org or user.
+    pub fn fetch_account_clone_urls(
+        &self,
+        account: &str,
+        symbol: &str,
+    ) -> anyhow::Result<Vec<CloneUrl>> {
+        let OrganizationRepoUrl(url) = self.resolve_account_repos_url(account)?;
+        let git_repos = Self::get_all_repos(&self.http, &self.token, &url)?;
+
+        let clone_urls = git_repos
+            .into_iter()
+            .map(|repo| self.clone_url_of(repo, symbol))
             .collect();
 
-        let mut git_urls: Vec<CloneUrl> = vec![];
+        Ok(clone_urls)
+    }
 
-        for OrganizationRepoUrl(url) in repo_urls {
-            let git_repos = Self::get_all_repos(&self.token, url.as_str())?;
+    /// Orgs and users list their repos at different endpoints. Accounts not
+    /// configured in `extra_orgs` or `extra_users` are looked up to tell them
+    /// apart.
+    fn resolve_account_repos_url(&self, account: &str) -> anyhow::Result<OrganizationRepoUrl> {
+        let is_configured = self
+            .extra_orgs
+            .iter()
+            .chain(self.extra_users.iter())
+            .any(|configured| configured.eq_ignore_ascii_case(account));
 
-            for Repo {
-                name: _,
-                full_name: _,
-                description: _,
-                ssh_url,
-            } in git_repos
-            {
-                // println!("{ssh_url}");
-                git_urls.push(CloneUrl(ssh_url, symbol.to_string()))
-            }
+        if is_configured {
+            return Ok(self.account_repos_url(account));
         }
 
-        Ok(git_urls)
+        let account_url = format!("{}/users/{}", &self.api_url, account);
+        let found = self
+            .http
+            .get_page(&account_url, |request| request.bearer_auth(&self.token))
+            .and_then(|page| Ok(page.json::<Account>()?))
+            .map_err(|e| KloniError::UnknownAccount(account.to_string(), e.to_string()))?;
+
+        let url = match found.kind.as_str() {
+            "Organization" => format!("{}/orgs/{}/repos", &self.api_url, account),
+            _ => format!("{}/users/{}/repos", &self.api_url, account),
+        };
+
+        Ok(OrganizationRepoUrl(url))
     }
 
-    pub fn get_all_organizations(token: &str, url: &str) -> anyhow::Result<Vec<Organization>> {
-        let response = RequestBuilder::try_new(Method::GET, url)
-            .map_err(|_e| HttpProblem::InvalidUrl(url.to_string()))?
-            .danger_accept_invalid_certs(true)
-            // .header("Accept", "application/vnd.github+json")
-            .bearer_auth(token)
-            .send()
-            .map_err(|e| HttpProblem::RequestFailed(url.to_string(), e.to_string()))?;
+    fn account_repos_url(&self, account: &str) -> OrganizationRepoUrl {
+        let is_user = self
+            .extra_users
+            .iter()
+            .any(|user| user.eq_ignore_ascii_case(account));
 
-        if !response.is_success() {
-            return Err(HttpProblem::RequestFailed(
-                url.to_string(),
-                format!("status: {}", response.status()),
-            )
-            .into());
+        match is_user {
+            true => OrganizationRepoUrl(format!("{}/users/{}/repos", &self.api_url, account)),
+            false => OrganizationRepoUrl(format!("{}/orgs/{}/repos", &self.api_url, account)),
         }
+    }
 
-        let deserialized_result = response
-            .json::<Vec<Organization>>()
-            .map_err(|e| HttpProblem::DeserializationFailed(url.to_string(), e.to_string()))?;
+    fn clone_url_of(&self, repo: Repo, symbol: &str) -> CloneUrl {
+        let url = match self.protocol {
+            Protocol::ssh => repo.ssh_url,
+            Protocol::https => repo.clone_url.unwrap_or(repo.ssh_url),
+        };
+
+        CloneUrl::new(url, symbol.to_string()).with_meta(RepoMeta {
+            name: Some(repo.name),
+            full_name: Some(repo.full_name),
+            description: repo.description,
+            default_branch: repo.default_branch,
+            archived: repo.archived,
+            fork: repo.fork,
+            created_at: repo.created_at,
+            pushed_at: repo.pushed_at,
+            // older Github Enterprise versions only tell `private`
+            visibility: repo
+                .visibility
+                .or(repo.private.map(|private| match private {
+                    true => "private".to_string(),
+                    false => "public".to_string(),
+                })),
+            size: repo.size,
+            tags: repo.topics,
+        })
+    }
+
+    pub fn get_all_organizations(
+        http: &HttpSettings,
+        token: &str,
+        url: &str,
+    ) -> anyhow::Result<Vec<Organization>> {
+        Self::get_all_pages::<Organization>(http, token, url)
+    }
 
-        Ok(deserialized_result)
+    pub fn get_all_repos(http: &HttpSettings, token: &str, url: &str) -> anyhow::Result<Vec<Repo>> {
+        Self::get_all_pages::<Repo>(http, token, url)
     }
 
-    pub fn get_all_repos(token: &str, url: &str) -> anyhow::Result<Vec<Repo>> {
+    /// Follows the `Link` header through all pages. Pages the server reports
+    /// as unchanged are served from `http.pages` and don't count against the
+    /// rate limit.
+    pub fn get_all_pages<T: DeserializeOwned>(
+        http: &HttpSettings,
+        token: &str,
+        url: &str,
+    ) -> anyhow::Result<Vec<T>> {
         let mut pages_remaining = true;
         let mut results = vec![];
         let mut request_url = url.to_string();
@@ -103,23 +292,9 @@ impl Github {
         while pages_remaining {
             // println!("calling: {request_url}");
 
-            let response = RequestBuilder::try_new(Method::GET, &request_url)
-                .map_err(|_e| HttpProblem::InvalidUrl(request_url.to_string()))?
-                .danger_accept_invalid_certs(true)
-                // .header("Accept", "application/vnd.github+json")
-                .bearer_auth(token)
-                .send()
-                .map_err(|e| HttpProblem::RequestFailed(request_url.to_string(), e.to_string()))?;
-
-            if !response.is_success() {
-                return Err(HttpProblem::RequestFailed(
-                    request_url,
-                    format!("status: {}", response.status()),
-                )
-                .into());
-            }
+            let page = http.get_page(&request_url, |request| request.bearer_auth(token))?;
 
-            pages_remaining = match Self::next_link(response.headers()) {
+            pages_remaining = match Self::next_link(&page.headers) {
                 Some(next_link) => {
                     request_url = next_link;
                     true
@@ -129,11 +304,7 @@ impl Github {
 
             // println!("pages remaining: {pages_remaining}");
 
-            let deserialized_result = response.json::<Vec<Repo>>().map_err(|e| {
-                HttpProblem::DeserializationFailed(request_url.to_string(), e.to_string())
-            })?;
-
-            results.extend(deserialized_result);
+            results.extend(page.json::<Vec<T>>()?);
         }
 
         Ok(results)
@@ -159,6 +330,18 @@ impl HttpProvider for Github {
         let result = self.fetch_clone_urls(symbol)?;
         Ok(result)
     }
+
+    fn request_groups_from_remote(&self, symbol: &str) -> anyhow::Result<Vec<UrlGroup>> {
+        self.fetch_grouped_clone_urls(symbol)
+    }
+
+    fn request_group_from_remote(
+        &self,
+        group: &str,
+        symbol: &str,
+    ) -> Option<anyhow::Result<Vec<CloneUrl>>> {
+        Some(self.fetch_account_clone_urls(group, symbol))
+    }
 }
 
 impl FileProvider for Github {
@@ -176,13 +359,26 @@ impl GitUrlProvider for Github {
 #[cfg(test)]
 mod tests {
 
+    use crate::files::config::GithubSource;
     use crate::github::Github;
     use attohttpc::header::HeaderMap;
 
-    use crate::core::CloneUrl;
+    use crate::core::{CloneUrl, HttpProvider, RepoMeta};
     use httpmock::prelude::*;
     use serde_json::json;
 
+    #[test]
+    pub fn should_detect_public_github_api_url() {
+        assert_eq!(
+            Github::default_api_url("https://github.com/"),
+            "https://api.github.com"
+        );
+        assert_eq!(
+            Github::default_api_url("https://git.acme-enterprise.org"),
+            "https://git.acme-enterprise.org/api/v3"
+        );
+    }
+
     #[test]
     pub fn should_successfully_extract_next_link_from_header() {
         let valid_link_header_value = r#"<https://acme.company.com/api/v3/organizations/12729/repos?per_page=20&page=2>; rel="next", <https://acme.company.com/api/v3/organizations/12729/repos?per_page=20&page=4>; rel="last""#;
@@ -226,8 +422,8 @@ mod tests {
         let server = MockServer::start();
         let address = server.address().to_string();
 
+        let api_url = format!("http://{address}/api/v3");
         let user_orgs_path = "/api/v3/user/orgs";
-        let user_orgs_url = format!("http://{address}{user_orgs_path}");
 
         let foo_org_repos_path = "/api/v3/orgs/FOO_ORG/repos";
         let foo_org_repos_url = format!("http://{address}{foo_org_repos_path}");
@@ -273,7 +469,14 @@ mod tests {
                             "name": "fanzy-project",
                             "full_name": "FOO_ORG/fanzy-project",
                             "description": "A fanzy project",
-                            "ssh_url": "git@localhost:FOO_ORG/fanzy-project.git"
+                            "ssh_url": "git@localhost:FOO_ORG/fanzy-project.git",
+                            "default_branch": "main",
+                            "archived": false,
+                            "fork": true,
+                            "pushed_at": "2024-01-15T08:30:00Z",
+                            "private": true,
+                            "size": 2048,
+                            "topics": ["fanzy"]
                           }
                         ]
                     )
@@ -305,7 +508,7 @@ mod tests {
                 );
         });
 
-        let github = Github::new("s3cr3t".to_string(), user_orgs_url, None);
+        let github = Github::new("s3cr3t".to_string(), api_url, None);
         let cloneable_urls = github.fetch_clone_urls("").unwrap();
 
         user_organizations_mock.assert();
@@ -314,28 +517,384 @@ mod tests {
 
         assert_eq!(&cloneable_urls.len(), &2);
         assert_eq!(
-            cloneable_urls.get(0),
-            Some(&CloneUrl(
-                "git@localhost:FOO_ORG/fanzy-project.git".to_string(),
-                "".to_string()
-            ))
+            cloneable_urls.first(),
+            Some(
+                &CloneUrl::new(
+                    "git@localhost:FOO_ORG/fanzy-project.git".to_string(),
+                    "".to_string()
+                )
+                .with_meta(RepoMeta {
+                    name: Some("fanzy-project".to_string()),
+                    full_name: Some("FOO_ORG/fanzy-project".to_string()),
+                    description: Some("A fanzy project".to_string()),
+                    default_branch: Some("main".to_string()),
+                    archived: Some(false),
+                    fork: Some(true),
+                    created_at: None,
+                    pushed_at: Some("2024-01-15T08:30:00Z".to_string()),
+                    visibility: Some("private".to_string()),
+                    size: Some(2048),
+                    tags: vec!["fanzy".to_string()],
+                })
+            )
+        );
+        assert_eq!(
+            cloneable_urls.get(1).map(|url| url.0.as_str()),
+            Some("git@localhost:FOO_ORG/fanzy-project-2.git")
         );
+    }
+
+    #[test]
+    pub fn should_follow_link_header_through_all_organizations() {
+        let server = MockServer::start();
+        let address = server.address().to_string();
+        let api_url = format!("http://{address}/api/v3");
+
+        let next_link = format!("<http://{address}/api/v3/user/orgs/more?page=2>; rel=\"next\"");
+
+        let user_organizations_mock_page_1 = server.mock(|when, then| {
+            when.method("GET").path("/api/v3/user/orgs");
+            then.status(200).header("Link", next_link).json_body(
+                json!([{ "repos_url": format!("http://{address}/api/v3/orgs/FOO_ORG/repos") }]),
+            );
+        });
+
+        let user_organizations_mock_page_2 = server.mock(|when, then| {
+            when.method("GET")
+                .path("/api/v3/user/orgs/more")
+                .query_param("page", "2");
+            then.status(200).json_body(
+                json!([{ "repos_url": format!("http://{address}/api/v3/orgs/BAR_ORG/repos") }]),
+            );
+        });
+
+        let org_repos_mocks = ["FOO_ORG", "BAR_ORG"].map(|org| {
+            server.mock(|when, then| {
+                when.method("GET").path(format!("/api/v3/orgs/{org}/repos"));
+                then.status(200).json_body(json!([{
+                    "name": "repo",
+                    "full_name": format!("{org}/repo"),
+                    "ssh_url": format!("git@localhost:{org}/repo.git")
+                }]));
+            })
+        });
+
+        let github = Github::new("s3cr3t".to_string(), api_url, None);
+        let cloneable_urls = github.fetch_clone_urls("").unwrap();
+
+        user_organizations_mock_page_1.assert();
+        user_organizations_mock_page_2.assert();
+        org_repos_mocks.iter().for_each(|mock| mock.assert());
+
         assert_eq!(
-            cloneable_urls.get(1),
-            Some(&CloneUrl(
-                "git@localhost:FOO_ORG/fanzy-project-2.git".to_string(),
-                "".to_string()
-            ))
+            cloneable_urls
+                .iter()
+                .map(|url| url.0.as_str())
+                .collect::<Vec<&str>>(),
+            vec![
+                "git@localhost:FOO_ORG/repo.git",
+                "git@localhost:BAR_ORG/repo.git"
+            ]
         );
     }
 
+    #[test]
+    pub fn should_fetch_user_starred_and_extra_repos_without_duplicates() {
+        let server = MockServer::start();
+        let address = server.address().to_string();
+        let api_url = format!("http://{address}/api/v3");
+
+        let user_repos_mock = server.mock(|when, then| {
+            when.method("GET")
+                .header("Authorization", "Bearer s3cr3t")
+                .path("/api/v3/user/repos")
+                .query_param("affiliation", "owner,collaborator,organization_member");
+            then.status(200)
+                .header("content-type", "application/json; charset=utf-8")
+                .body(
+                    json!(
+                        [
+                          {
+                            "name": "dotfiles",
+                            "full_name": "jdoe/dotfiles",
+                            "description": null,
+                            "ssh_url": "git@localhost:jdoe/dotfiles.git"
+                          },
+                          {
+                            "name": "fanzy-project",
+                            "full_name": "FOO_ORG/fanzy-project",
+                            "description": "A fanzy project",
+                            "ssh_url": "git@localhost:FOO_ORG/fanzy-project.git"
+                          }
+                        ]
+                    )
+                    .to_string(),
+                );
+        });
+
+        let starred_mock = server.mock(|when, then| {
+            when.method("GET")
+                .header("Authorization", "Bearer s3cr3t")
+                .path("/api/v3/user/starred");
+            then.status(200)
+                .header("content-type", "application/json; charset=utf-8")
+                .body(
+                    json!(
+                        [
+                          {
+                            "name": "fanzy-project",
+                            "full_name": "FOO_ORG/fanzy-project",
+                            "description": "A fanzy project",
+                            "ssh_url": "git@localhost:FOO_ORG/fanzy-project.git"
+                          }
+                        ]
+                    )
+                    .to_string(),
+                );
+        });
+
+        let extra_org_mock = server.mock(|when, then| {
+            when.method("GET").path("/api/v3/orgs/BAR_ORG/repos");
+            then.status(200)
+                .header("content-type", "application/json; charset=utf-8")
+                .body(
+                    json!(
+                        [
+                          {
+                            "name": "bar",
+                            "full_name": "BAR_ORG/bar",
+                            "description": null,
+                            "ssh_url": "git@localhost:BAR_ORG/bar.git"
+                          }
+                        ]
+                    )
+                    .to_string(),
+                );
+        });
+
+        let extra_user_mock = server.mock(|when, then| {
+            when.method("GET").path("/api/v3/users/octocat/repos");
+            then.status(200)
+                .header("content-type", "application/json; charset=utf-8")
+                .body(
+                    json!(
+                        [
+                          {
+                            "name": "hello-world",
+                            "full_name": "octocat/hello-world",
+                            "description": null,
+                            "ssh_url": "git@localhost:octocat/hello-world.git"
+                          }
+                        ]
+                    )
+                    .to_string(),
+                );
+        });
+
+        let github = Github::new("s3cr3t".to_string(), api_url, None).with_sources(
+            vec![GithubSource::user_repos, GithubSource::starred],
+            vec!["BAR_ORG".to_string()],
+            vec!["octocat".to_string()],
+        );
+        let cloneable_urls = github.fetch_clone_urls("").unwrap();
+
+        user_repos_mock.assert();
+        starred_mock.assert();
+        extra_org_mock.assert();
+        extra_user_mock.assert();
+
+        let urls: Vec<&str> = cloneable_urls.iter().map(|url| url.0.as_str()).collect();
+        assert_eq!(
+            urls,
+            vec![
+                "git@localhost:jdoe/dotfiles.git",
+                "git@localhost:FOO_ORG/fanzy-project.git",
+                "git@localhost:BAR_ORG/bar.git",
+                "git@localhost:octocat/hello-world.git",
+            ]
+        );
+    }
+
+    #[test]
+    pub fn should_fetch_repos_of_single_org_or_extra_user() {
+        let server = MockServer::start();
+        let address = server.address().to_string();
+        let api_url = format!("http://{address}/api/v3");
+
+        let repo = |full_name: &str| {
+            json!([{
+                "name": full_name.split('/').next_back(),
+                "full_name": full_name,
+                "description": null,
+                "ssh_url": format!("git@localhost:{full_name}.git")
+            }])
+            .to_string()
+        };
+
+        let org_mock = server.mock(|when, then| {
+            when.method("GET")
+                .header("Authorization", "Bearer s3cr3t")
+                .path("/api/v3/orgs/FOO_ORG/repos");
+            then.status(200)
+                .header("content-type", "application/json; charset=utf-8")
+                .body(repo("FOO_ORG/fanzy-project"));
+        });
+
+        let user_mock = server.mock(|when, then| {
+            when.method("GET").path("/api/v3/users/octocat/repos");
+            then.status(200)
+                .header("content-type", "application/json; charset=utf-8")
+                .body(repo("octocat/hello-world"));
+        });
+
+        let account = |kind: &str| json!({ "type": kind }).to_string();
+
+        let org_lookup_mock = server.mock(|when, then| {
+            when.method("GET")
+                .header("Authorization", "Bearer s3cr3t")
+                .path("/api/v3/users/FOO_ORG");
+            then.status(200)
+                .header("content-type", "application/json; charset=utf-8")
+                .body(account("Organization"));
+        });
+
+        let user_lookup_mock = server.mock(|when, then| {
+            when.method("GET").path("/api/v3/users/hubot");
+            then.status(200)
+                .header("content-type", "application/json; charset=utf-8")
+                .body(account("User"));
+        });
+
+        let unlisted_user_mock = server.mock(|when, then| {
+            when.method("GET").path("/api/v3/users/hubot/repos");
+            then.status(200)
+                .header("content-type", "application/json; charset=utf-8")
+                .body(repo("hubot/chatops"));
+        });
+
+        let github = Github::new("s3cr3t".to_string(), api_url, None).with_sources(
+            vec![GithubSource::orgs],
+            vec![],
+            vec!["octocat".to_string()],
+        );
+
+        let org_urls = github.request_group_from_remote("FOO_ORG", "GH").unwrap();
+        let user_urls = github.request_group_from_remote("octocat", "GH").unwrap();
+        let unlisted_user_urls = github.request_group_from_remote("hubot", "GH").unwrap();
+
+        assert_eq!(
+            org_urls.unwrap().first().map(|url| url.0.to_owned()),
+            Some("git@localhost:FOO_ORG/fanzy-project.git".to_string())
+        );
+        assert_eq!(
+            user_urls.unwrap().first().map(|url| url.0.to_owned()),
+            Some("git@localhost:octocat/hello-world.git".to_string())
+        );
+        assert_eq!(
+            unlisted_user_urls
+                .unwrap()
+                .first()
+                .map(|url| url.0.to_owned()),
+            Some("git@localhost:hubot/chatops.git".to_string())
+        );
+
+        org_mock.assert();
+        user_mock.assert();
+        org_lookup_mock.assert();
+        user_lookup_mock.assert();
+        unlisted_user_mock.assert();
+    }
+
+    #[test]
+    pub fn should_name_unknown_accounts() {
+        let server = MockServer::start();
+        let address = server.address().to_string();
+        let api_url = format!("http://{address}/api/v3");
+
+        let lookup_mock = server.mock(|when, then| {
+            when.method("GET").path("/api/v3/users/nobody");
+            then.status(404)
+                .header("content-type", "application/json; charset=utf-8")
+                .body(json!({ "message": "Not Found" }).to_string());
+        });
+
+        let github = Github::new("s3cr3t".to_string(), api_url, None);
+
+        let error = github
+            .request_group_from_remote("nobody", "GH")
+            .unwrap()
+            .unwrap_err();
+
+        assert!(error
+            .to_string()
+            .starts_with("No Github org or user 'nobody' found"));
+
+        lookup_mock.assert();
+    }
+
+    #[test]
+    pub fn should_reuse_unchanged_pages_on_not_modified() {
+        let server = MockServer::start();
+        let address = server.address().to_string();
+        let api_url = format!("http://{address}/api/v3");
+
+        let fresh_mock = server.mock(|when, then| {
+            when.method("GET")
+                .path("/api/v3/orgs/FOO_ORG/repos")
+                .matches(|req| {
+                    !req.headers
+                        .as_ref()
+                        .map(|headers| {
+                            headers
+                                .iter()
+                                .any(|(k, _)| k.eq_ignore_ascii_case("If-None-Match"))
+                        })
+                        .unwrap_or(false)
+                });
+            then.status(200)
+                .header("content-type", "application/json; charset=utf-8")
+                .header("ETag", "W/\"f00\"")
+                .body(
+                    json!([{
+                        "name": "fanzy-project",
+                        "full_name": "FOO_ORG/fanzy-project",
+                        "description": "A fanzy project",
+                        "ssh_url": "git@localhost:FOO_ORG/fanzy-project.git"
+                    }])
+                    .to_string(),
+                );
+        });
+
+        let not_modified_mock = server.mock(|when, then| {
+            when.method("GET")
+                .path("/api/v3/orgs/FOO_ORG/repos")
+                .header("If-None-Match", "W/\"f00\"");
+            then.status(304);
+        });
+
+        let github = Github::new("s3cr3t".to_string(), api_url, None).with_sources(
+            vec![GithubSource::orgs],
+            vec!["FOO_ORG".to_string()],
+            vec![],
+        );
+
+        let first = github.fetch_account_clone_urls("FOO_ORG", "").unwrap();
+        let second = github.fetch_account_clone_urls("FOO_ORG", "").unwrap();
+
+        fresh_mock.assert_hits(1);
+        not_modified_mock.assert_hits(1);
+
+        assert_eq!(first.len(), 1);
+        assert_eq!(first, second);
+    }
+
     #[test]
     fn should_fail_when_org_repos_json_response_is_not_parsable() {
         let server = MockServer::start();
         let address = server.address().to_string();
 
+        let api_url = format!("http://{address}/api/v3");
         let user_orgs_path = "/api/v3/user/orgs";
-        let user_orgs_url = format!("http://{address}{user_orgs_path}");
 
         let foo_org_repos_path = "/api/v3/orgs/FOO_ORG/repos";
         let foo_org_repos_url = format!("http://{address}{foo_org_repos_path}");
@@ -368,7 +927,7 @@ mod tests {
                 .body("{");
         });
 
-        let github = Github::new("s3cr3t".to_string(), user_orgs_url, None);
+        let github = Github::new("s3cr3t".to_string(), api_url, None);
         let result = github.fetch_clone_urls("");
 
         user_organizations_mock.assert();
@@ -385,8 +944,9 @@ mod tests {
         let server = MockServer::start();
         let address = server.address().to_string();
 
+        let api_url = format!("http://{address}/api/v3");
         let user_orgs_path = "/api/v3/user/orgs";
-        let user_orgs_url = format!("http://{address}{user_orgs_path}");
+        let user_orgs_url = format!("{api_url}/user/orgs");
 
         let foo_org_repos_path = "/api/v3/orgs/FOO_ORG/repos";
 
@@ -421,7 +981,7 @@ mod tests {
                 );
         });
 
-        let github = Github::new("s3cr3t".to_string(), user_orgs_url.clone(), None);
+        let github = Github::new("s3cr3t".to_string(), api_url, None);
         let result = github.fetch_clone_urls("");
 
         user_organizations_mock.assert();
@@ -443,6 +1003,9 @@ mod tests {
 
         assert!(result.is_err());
 
-        assert_eq!(format!("{}", result.unwrap_err()), "Invalid url 'bonkers'");
+        assert_eq!(
+            format!("{}", result.unwrap_err()),
+            "Invalid url 'bonkers/user/orgs'"
+        );
     }
 }
@@ -0,0 +1,412 @@
+use std::collections::HashSet;
+
+use attohttpc::header::HeaderMap;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::de::{DeserializeOwned, IgnoredAny};
+use serde::Deserialize;
+
+use crate::core::{CloneUrl, FileProvider, GitUrlProvider, HttpProblem, HttpProvider, RepoMeta};
+use crate::files::config::Protocol;
+use crate::http::HttpSettings;
+
+#[derive(Debug, Deserialize)]
+pub struct Group {
+    pub id: u64,
+    pub full_path: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Project {
+    pub name: String,
+    pub path_with_namespace: String,
+    pub description: Option<String>,
+    pub ssh_url_to_repo: String,
+    pub http_url_to_repo: Option<String>,
+    pub default_branch: Option<String>,
+    pub archived: Option<bool>,
+    /// Only present for forks
+    pub forked_from_project: Option<IgnoredAny>,
+    pub created_at: Option<String>,
+    pub visibility: Option<String>,
+    #[serde(default)]
+    pub topics: Vec<String>,
+}
+
+pub struct Gitlab {
+    pub token: String,
+    pub groups_url: String,
+    pub symbol: Option<String>,
+    pub protocol: Protocol,
+    pub http: HttpSettings,
+}
+
+lazy_static! {
+    static ref NEXT_LINK_REGEX: Regex = Regex::new(r#"(?i)<([^>]+)>;\s*rel="next""#).unwrap();
+}
+
+pub const API_PATH: &str = "/api/v4";
+
+pub const GROUPS_PATH: &str = "/groups";
+
+const PER_PAGE: &str = "100";
+
+impl Gitlab {
+    pub fn new(token: String, groups_url: String, symbol: Option<String>) -> Gitlab {
+        Gitlab {
+            token,
+            groups_url,
+            symbol,
+            protocol: Protocol::ssh,
+            http: HttpSettings::default(),
+        }
+    }
+
+    pub fn with_protocol(mut self, protocol: Protocol) -> Gitlab {
+        self.protocol = protocol;
+        self
+    }
+
+    pub fn with_http(mut self, http: HttpSettings) -> Gitlab {
+        self.http = http;
+        self
+    }
+
+    pub fn fetch_clone_urls(&self, symbol: &str) -> anyhow::Result<Vec<CloneUrl>> {
+        let groups = Self::get_all_groups(&self.http, &self.token, &self.groups_url)?;
+
+        let mut git_urls: Vec<CloneUrl> = vec![];
+        let mut seen_urls: HashSet<String> = HashSet::new();
+
+        for Group { id, full_path: _ } in groups {
+            let projects_url = format!("{}/{}/projects", &self.groups_url, id);
+            let projects = Self::get_all_pages::<Project>(&self.http, &self.token, &projects_url)?;
+
+            for project in projects {
+                let clone_url = self.clone_url_of(project, symbol);
+
+                if seen_urls.insert(clone_url.0.clone()) {
+                    git_urls.push(clone_url)
+                }
+            }
+        }
+
+        Ok(git_urls)
+    }
+
+    fn clone_url_of(&self, project: Project, symbol: &str) -> CloneUrl {
+        let url = match self.protocol {
+            Protocol::ssh => project.ssh_url_to_repo,
+            Protocol::https => project.http_url_to_repo.unwrap_or(project.ssh_url_to_repo),
+        };
+
+        CloneUrl::new(url, symbol.to_string()).with_meta(RepoMeta {
+            name: Some(project.name),
+            full_name: Some(project.path_with_namespace),
+            description: project.description,
+            default_branch: project.default_branch,
+            archived: project.archived,
+            fork: Some(project.forked_from_project.is_some()),
+            created_at: project.created_at,
+            visibility: project.visibility,
+            tags: project.topics,
+            ..RepoMeta::default()
+        })
+    }
+
+    /// Collects every group visible to the token and recursively descends
+    /// into their subgroups. Each group is returned exactly once.
+    pub fn get_all_groups(
+        http: &HttpSettings,
+        token: &str,
+        groups_url: &str,
+    ) -> anyhow::Result<Vec<Group>> {
+        let mut pending = Self::get_all_pages::<Group>(http, token, groups_url)?;
+        let mut seen_ids: HashSet<u64> = HashSet::new();
+        let mut groups = vec![];
+
+        while let Some(group) = pending.pop() {
+            if !seen_ids.insert(group.id) {
+                continue;
+            }
+
+            let subgroups_url = format!("{}/{}/subgroups", groups_url, group.id);
+            pending.extend(Self::get_all_pages::<Group>(http, token, &subgroups_url)?);
+
+            groups.push(group);
+        }
+
+        groups.sort_by(|a, b| a.full_path.cmp(&b.full_path));
+
+        Ok(groups)
+    }
+
+    pub fn get_all_pages<T: DeserializeOwned>(
+        http: &HttpSettings,
+        token: &str,
+        url: &str,
+    ) -> anyhow::Result<Vec<T>> {
+        let mut results = vec![];
+        let mut request_url = url.to_string();
+        let mut page: Option<String> = None;
+
+        loop {
+            let mut request = http.get(&request_url)?.header("PRIVATE-TOKEN", token);
+
+            if !request_url.contains("per_page=") {
+                request = request.param("per_page", PER_PAGE);
+            }
+
+            if let Some(page) = &page {
+                request = request.param("page", page);
+            }
+
+            let response = request
+                .send()
+                .map_err(|e| HttpProblem::RequestFailed(request_url.to_string(), e.to_string()))?;
+
+            if !response.is_success() {
+                return Err(HttpProblem::RequestFailed(
+                    request_url,
+                    format!("status: {}", response.status()),
+                )
+                .into());
+            }
+
+            let next_link = Self::next_link(response.headers());
+            let next_page = Self::next_page(response.headers());
+
+            let deserialized_result = response.json::<Vec<T>>().map_err(|e| {
+                HttpProblem::DeserializationFailed(request_url.to_string(), e.to_string())
+            })?;
+
+            results.extend(deserialized_result);
+
+            match (next_link, next_page) {
+                (Some(link), _) => {
+                    request_url = link;
+                    page = None;
+                }
+                (None, Some(next_page)) => page = Some(next_page),
+                (None, None) => break,
+            }
+        }
+
+        Ok(results)
+    }
+
+    fn next_link(header_map: &HeaderMap) -> Option<String> {
+        let value = header_map.get("Link")?.to_str().ok()?;
+        let captures = NEXT_LINK_REGEX.captures(value)?;
+
+        captures.get(1).map(|link| link.as_str().to_string())
+    }
+
+    /// GitLab omits the `Link` header for some keyset-less endpoints but always
+    /// sends `X-Next-Page`, which is empty on the last page.
+    fn next_page(header_map: &HeaderMap) -> Option<String> {
+        let value = header_map.get("X-Next-Page")?.to_str().ok()?.trim();
+
+        match value.is_empty() {
+            true => None,
+            false => Some(value.to_string()),
+        }
+    }
+}
+
+impl HttpProvider for Gitlab {
+    fn request_from_remote(&self, symbol: &str) -> anyhow::Result<Vec<CloneUrl>> {
+        let result = self.fetch_clone_urls(symbol)?;
+        Ok(result)
+    }
+}
+
+impl FileProvider for Gitlab {
+    fn name(&self) -> &str {
+        "gitlab"
+    }
+}
+
+impl GitUrlProvider for Gitlab {
+    fn symbol(&self) -> String {
+        self.symbol.to_owned().unwrap_or("".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::core::{CloneUrl, RepoMeta};
+    use crate::gitlab::Gitlab;
+    use attohttpc::header::HeaderMap;
+    use httpmock::prelude::*;
+    use serde_json::json;
+
+    #[test]
+    pub fn should_successfully_extract_next_link_from_header() {
+        let valid_link_header_value = r#"<https://gitlab.acme.com/api/v4/groups?page=2&per_page=100>; rel="next", <https://gitlab.acme.com/api/v4/groups?page=3&per_page=100>; rel="last""#;
+
+        let mut hm = HeaderMap::new();
+        hm.insert("Link", valid_link_header_value.parse().unwrap());
+
+        assert_eq!(
+            Gitlab::next_link(&hm),
+            Some("https://gitlab.acme.com/api/v4/groups?page=2&per_page=100".to_owned())
+        );
+    }
+
+    #[test]
+    pub fn should_extract_nothing_if_next_page_header_is_empty() {
+        let mut hm = HeaderMap::new();
+        hm.insert("X-Next-Page", "".parse().unwrap());
+
+        assert_eq!(Gitlab::next_page(&hm), None);
+    }
+
+    #[test]
+    pub fn should_fetch_clone_urls_of_groups_and_subgroups() {
+        let server = MockServer::start();
+        let address = server.address().to_string();
+
+        let groups_path = "/api/v4/groups";
+        let groups_url = format!("http://{address}{groups_path}");
+
+        let groups_mock = server.mock(|when, then| {
+            when.method("GET")
+                .header("PRIVATE-TOKEN", "s3cr3t")
+                .path(groups_path);
+            then.status(200)
+                .header("content-type", "application/json")
+                .header("X-Next-Page", "")
+                .body(json!([{ "id": 1, "full_path": "platform" }]).to_string());
+        });
+
+        let subgroups_mock = server.mock(|when, then| {
+            when.method("GET").path("/api/v4/groups/1/subgroups");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(json!([{ "id": 2, "full_path": "platform/tools" }]).to_string());
+        });
+
+        let nested_subgroups_mock = server.mock(|when, then| {
+            when.method("GET").path("/api/v4/groups/2/subgroups");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body("[]");
+        });
+
+        let projects_page_1_mock = server.mock(|when, then| {
+            when.method("GET")
+                .path("/api/v4/groups/1/projects")
+                .matches(|req| {
+                    !req.query_params
+                        .as_ref()
+                        .map(|params| params.iter().any(|(k, _)| k == "page"))
+                        .unwrap_or(false)
+                });
+            then.status(200)
+                .header("content-type", "application/json")
+                .header("X-Next-Page", "2")
+                .body(
+                    json!([{
+                        "name": "api",
+                        "path_with_namespace": "platform/api",
+                        "description": "The API",
+                        "ssh_url_to_repo": "git@localhost:platform/api.git",
+                        "default_branch": "main",
+                        "archived": false,
+                        "forked_from_project": { "id": 7 },
+                        "created_at": "2023-05-02T09:12:44.000Z",
+                        "visibility": "internal",
+                        "topics": ["backend"]
+                    }])
+                    .to_string(),
+                );
+        });
+
+        let projects_page_2_mock = server.mock(|when, then| {
+            when.method("GET")
+                .path("/api/v4/groups/1/projects")
+                .query_param("page", "2");
+            then.status(200)
+                .header("content-type", "application/json")
+                .header("X-Next-Page", "")
+                .body(
+                    json!([{
+                        "name": "web",
+                        "path_with_namespace": "platform/web",
+                        "description": null,
+                        "ssh_url_to_repo": "git@localhost:platform/web.git"
+                    }])
+                    .to_string(),
+                );
+        });
+
+        let subgroup_projects_mock = server.mock(|when, then| {
+            when.method("GET").path("/api/v4/groups/2/projects");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(
+                    json!([{
+                        "name": "cli",
+                        "path_with_namespace": "platform/tools/cli",
+                        "description": null,
+                        "ssh_url_to_repo": "git@localhost:platform/tools/cli.git"
+                    }])
+                    .to_string(),
+                );
+        });
+
+        let gitlab = Gitlab::new("s3cr3t".to_string(), groups_url, None);
+        let cloneable_urls = gitlab.fetch_clone_urls("GL").unwrap();
+
+        groups_mock.assert();
+        subgroups_mock.assert();
+        nested_subgroups_mock.assert();
+        projects_page_1_mock.assert();
+        projects_page_2_mock.assert();
+        subgroup_projects_mock.assert();
+
+        assert_eq!(
+            cloneable_urls,
+            vec![
+                CloneUrl::new(
+                    "git@localhost:platform/api.git".to_string(),
+                    "GL".to_string()
+                )
+                .with_meta(RepoMeta {
+                    name: Some("api".to_string()),
+                    full_name: Some("platform/api".to_string()),
+                    description: Some("The API".to_string()),
+                    default_branch: Some("main".to_string()),
+                    archived: Some(false),
+                    fork: Some(true),
+                    created_at: Some("2023-05-02T09:12:44.000Z".to_string()),
+                    visibility: Some("internal".to_string()),
+                    tags: vec!["backend".to_string()],
+                    ..RepoMeta::default()
+                }),
+                CloneUrl::new(
+                    "git@localhost:platform/web.git".to_string(),
+                    "GL".to_string()
+                )
+                .with_meta(RepoMeta {
+                    name: Some("web".to_string()),
+                    full_name: Some("platform/web".to_string()),
+                    fork: Some(false),
+                    ..RepoMeta::default()
+                }),
+                CloneUrl::new(
+                    "git@localhost:platform/tools/cli.git".to_string(),
+                    "GL".to_string()
+                )
+                .with_meta(RepoMeta {
+                    name: Some("cli".to_string()),
+                    full_name: Some("platform/tools/cli".to_string()),
+                    fork: Some(false),
+                    ..RepoMeta::default()
+                }),
+            ]
+        );
+    }
+}
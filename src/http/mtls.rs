@@ -0,0 +1,175 @@
+//! Requests presenting a client certificate (mutual TLS). The http client used
+//! for all other requests can't present one, so these requests are sent with
+//! a `ureq` agent whose TLS connector carries the client identity.
+
+use std::{
+    fmt::Display,
+    fs::read,
+    io::{self, Read},
+    path::Path,
+    sync::Arc,
+};
+
+use attohttpc::{
+    header::{HeaderMap, HeaderName, HeaderValue, LOCATION},
+    ErrorKind, RequestBuilder, StatusCode,
+};
+use native_tls::{Certificate, Identity, TlsConnector};
+use openssl::{pkey::PKey, x509::X509};
+use ureq::{AgentBuilder, Proxy};
+
+use crate::core::HttpProblem;
+
+use super::{HttpSettings, Response};
+
+const MAX_REDIRECTIONS: usize = 5;
+
+/// A client certificate, the intermediates to send along and its private key.
+#[derive(Clone)]
+pub struct ClientIdentity {
+    identity: Identity,
+}
+
+impl ClientIdentity {
+    /// Reads the PEM encoded certificate, optionally followed by its chain, and
+    /// the unencrypted private key. Without `key` the private key is expected
+    /// in the certificate file.
+    pub fn from_pem_files(
+        certificate: &Path,
+        key: Option<&Path>,
+    ) -> Result<ClientIdentity, HttpProblem> {
+        let invalid = |reason: String| {
+            HttpProblem::InvalidClientCertificate(certificate.display().to_string(), reason)
+        };
+
+        let pem = read(certificate).map_err(|e| invalid(e.to_string()))?;
+        let certificates = X509::stack_from_pem(&pem).map_err(|e| invalid(e.to_string()))?;
+
+        let Some(leaf) = certificates.first() else {
+            return Err(invalid("no certificate found".to_string()));
+        };
+
+        let key_pem = match key {
+            Some(key) => read(key).map_err(|e| invalid(format!("{}: {}", key.display(), e)))?,
+            None => pem.to_owned(),
+        };
+
+        // an empty passphrase keeps openssl from prompting for encrypted keys
+        let key = PKey::private_key_from_pem_passphrase(&key_pem, &[])
+            .map_err(|e| invalid(format!("can't read private key: {e}")))?;
+
+        let matches = leaf
+            .public_key()
+            .is_ok_and(|public_key| public_key.public_eq(&key));
+
+        if !matches {
+            return Err(invalid(
+                "private key doesn't belong to the certificate".to_string(),
+            ));
+        }
+
+        // the TLS connector only takes PKCS#8 keys, whatever format was given
+        let mut chain = vec![];
+        for certificate in &certificates {
+            chain.extend(certificate.to_pem().map_err(|e| invalid(e.to_string()))?);
+        }
+        let key = key
+            .private_key_to_pem_pkcs8()
+            .map_err(|e| invalid(e.to_string()))?;
+
+        Ok(ClientIdentity {
+            identity: Identity::from_pkcs8(&chain, &key).map_err(|e| invalid(e.to_string()))?,
+        })
+    }
+}
+
+/// Sends `request` presenting `identity`. Certificates are verified and
+/// proxies used like the http client does. Redirects are only followed within
+/// the same origin, so neither the credentials nor the client certificate are
+/// handed to another host.
+pub fn send(
+    request: RequestBuilder,
+    identity: &ClientIdentity,
+    http: &HttpSettings,
+) -> attohttpc::Result<Response> {
+    // bodies are read as they are, without decompression
+    let request = request.allow_compression(false).try_prepare()?;
+    let mut url = request.url().to_owned();
+
+    let mut tls = TlsConnector::builder();
+    tls.identity(identity.identity.to_owned())
+        .danger_accept_invalid_certs(!http.verify_tls)
+        .danger_accept_invalid_hostnames(!http.verify_tls);
+    for certificate in &http.ca_certificates {
+        let der = certificate.to_der().map_err(transport_error)?;
+        tls.add_root_certificate(Certificate::from_der(&der).map_err(transport_error)?);
+    }
+
+    let mut agent = AgentBuilder::new()
+        .tls_connector(Arc::new(tls.build().map_err(transport_error)?))
+        .redirects(0);
+    if let Some(proxy) = http.proxy.proxy_for(url.as_str()) {
+        agent = agent.proxy(Proxy::new(proxy.as_str()).map_err(transport_error)?);
+    }
+    let agent = agent.build();
+
+    for _ in 0..=MAX_REDIRECTIONS {
+        let mut call = agent.request_url(request.method().as_str(), &url);
+        for (name, value) in request.headers() {
+            if let Ok(value) = value.to_str() {
+                call = call.set(name.as_str(), value);
+            }
+        }
+
+        let response = match call.call() {
+            Ok(response) | Err(ureq::Error::Status(_, response)) => into_response(response)?,
+            Err(e) => return Err(transport_error(e)),
+        };
+
+        let location = response
+            .headers()
+            .get(LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .and_then(|location| url.join(location).ok())
+            .filter(|location| {
+                response.status().is_redirection() && location.origin() == url.origin()
+            });
+
+        match location {
+            Some(location) => url = location,
+            None => return Ok(response),
+        }
+    }
+
+    Err(ErrorKind::TooManyRedirections.into())
+}
+
+fn into_response(response: ureq::Response) -> attohttpc::Result<Response> {
+    let status = StatusCode::from_u16(response.status()).map_err(io::Error::other)?;
+
+    let mut headers = HeaderMap::new();
+    for name in response.headers_names() {
+        for value in response.all(&name) {
+            // like the http client, invalid headers are dropped
+            if let (Ok(name), Ok(value)) = (
+                HeaderName::from_bytes(name.as_bytes()),
+                HeaderValue::from_str(value),
+            ) {
+                headers.append(name, value);
+            }
+        }
+    }
+
+    let mut body = vec![];
+    response.into_reader().read_to_end(&mut body)?;
+
+    Ok(Response {
+        status,
+        headers,
+        body,
+    })
+}
+
+fn transport_error(e: impl Display) -> attohttpc::Error {
+    io::Error::other(e.to_string()).into()
+}
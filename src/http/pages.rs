@@ -0,0 +1,102 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::Write,
+    sync::{Arc, Mutex},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::files::{read_cache_file, replace_cache_file};
+
+/// A response body together with the validators the server sent for it and
+/// all its headers, which are replayed for `304 Not Modified` (e.g. `Link` or
+/// `X-Next-Page` for paging).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CachedPage {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+/// Remembers the pages of earlier api responses, so a refresh can ask the
+/// server whether a page changed (`If-None-Match`, `If-Modified-Since`)
+/// instead of downloading it again. Clones share their pages.
+///
+/// A persistent cache is read from `~/.cache/kloni/<file_name>` on first use
+/// and written back by `save`.
+#[derive(Clone, Default)]
+pub struct PageCache {
+    file_name: Option<String>,
+    state: Arc<Mutex<PageCacheState>>,
+}
+
+#[derive(Default)]
+struct PageCacheState {
+    loaded: bool,
+    pages: HashMap<String, CachedPage>,
+    visited: HashSet<String>,
+}
+
+impl PageCache {
+    pub fn persistent(file_name: String) -> PageCache {
+        PageCache {
+            file_name: Some(file_name),
+            state: Arc::default(),
+        }
+    }
+
+    pub fn lookup(&self, url: &str) -> Option<CachedPage> {
+        let mut state = self.state.lock().unwrap();
+        self.load(&mut state);
+
+        state.visited.insert(url.to_string());
+        state.pages.get(url).cloned()
+    }
+
+    pub fn store(&self, url: &str, page: CachedPage) {
+        let mut state = self.state.lock().unwrap();
+        self.load(&mut state);
+
+        state.visited.insert(url.to_string());
+        state.pages.insert(url.to_string(), page);
+    }
+
+    /// Writes the pages back to disk. `prune` drops all pages that weren't
+    /// requested since the last save, e.g. of orgs the user has left.
+    pub fn save(&self, prune: bool) -> anyhow::Result<()> {
+        let Some(file_name) = &self.file_name else {
+            return Ok(());
+        };
+
+        let mut state = self.state.lock().unwrap();
+        self.load(&mut state);
+
+        if prune {
+            let visited = std::mem::take(&mut state.visited);
+            state.pages.retain(|url, _| visited.contains(url));
+        }
+
+        let content = serde_json::to_string(&state.pages)?;
+
+        replace_cache_file(file_name, |file| {
+            write!(file, "{content}")?;
+            Ok(())
+        })
+    }
+
+    fn load(&self, state: &mut PageCacheState) {
+        if state.loaded {
+            return;
+        }
+
+        state.loaded = true;
+
+        // pages that can't be read are simply requested again
+        if let Some(content) = self.file_name.as_deref().and_then(read_cache_file) {
+            state.pages = serde_json::from_str(&content).unwrap_or_default();
+        }
+    }
+}
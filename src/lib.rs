@@ -1,82 +1,338 @@
+pub mod auth;
+pub mod azure_devops;
 pub mod bitbucket;
+pub mod bitbucket_cloud;
+pub mod cache;
+pub mod catalog;
+pub mod command;
 pub mod core;
 pub mod files;
+pub mod gerrit;
+pub mod gitea;
 pub mod github;
+pub mod gitlab;
+pub mod http;
+pub mod local;
+pub mod secrets;
 
-use std::{io::Cursor, path::Path};
+use std::{borrow::Cow, path::Path, sync::Arc};
 
-use git2::{build::RepoBuilder, Cred, FetchOptions, RemoteCallbacks};
+use git2::{build::RepoBuilder, FetchOptions, ProxyOptions, RemoteCallbacks};
 use skim::{
-    prelude::{Event, SkimItemReader, SkimOptionsBuilder},
-    Skim, SkimItem,
+    prelude::{unbounded, Event, SkimOptionsBuilder},
+    ItemPreview, PreviewContext, Skim, SkimItem, SkimItemReceiver, SkimItemSender,
 };
 
+use crate::auth::{expand_home, CloneAuth, HttpsCredentials};
+use crate::azure_devops::AzureDevOps;
 use crate::bitbucket::Bitbucket;
-use crate::core::{CloneUrl, GitUrlProvider};
-use crate::files::config::{Config, Type};
+use crate::bitbucket_cloud::BitbucketCloud;
+use crate::catalog::CatalogProvider;
+use crate::command::CommandProvider;
+use crate::core::{CloneUrl, Connection, GitUrlProvider, ProviderInstance};
+use crate::files::config::{BitbucketListing, Config, GithubSource, Protocol, Provider, Type};
+use crate::gerrit::Gerrit;
+use crate::gitea::Gitea;
 use crate::github::Github;
+use crate::gitlab::Gitlab;
+use crate::http::{pages::PageCache, HttpSettings, ProxyPolicy};
+use crate::local::Local;
+use crate::secrets::Secret;
 
-pub fn clone_url_provider_by_config(
-    config: &Config,
-) -> anyhow::Result<Vec<Box<dyn GitUrlProvider>>> {
-    let results = config
+/// Sets up the configured providers without resolving any of their tokens or
+/// reading their certificates yet. That happens once a provider has to be
+/// contacted, see [`ProviderInstance::connection`].
+pub fn clone_url_provider_by_config(config: &Config) -> Vec<ProviderInstance> {
+    config
         .providers
         .iter()
-        .filter_map(|provider| -> Option<Box<dyn GitUrlProvider>> {
-            let token = &provider.token;
-            let symbol = &provider.symbol;
-
-            match provider.provider {
-                Type::github => {
-                    let gh_base_url = format!("{}{}", &provider.base_url, github::USER_ORGS_PATH);
-                    Some(Box::new(Github::new(
-                        token.to_owned(),
-                        gh_base_url,
-                        symbol.to_owned(),
-                    )))
-                }
-
-                Type::bitbucket => {
-                    let bitbucket_base_url =
-                        format!("{}{}", &provider.base_url, bitbucket::USER_PROJECTS_PATH);
-                    Some(Box::new(Bitbucket::new(
-                        token.to_owned(),
-                        bitbucket_base_url,
-                        symbol.to_owned(),
-                    )))
-                }
-            }
+        .map(|provider| {
+            let settings = provider.clone();
+
+            ProviderInstance::new(provider.id(), move || connect(&settings))
+                .with_symbol(provider.symbol.to_owned().unwrap_or_default())
+                .with_cache_ttl(provider.cache_ttl())
+                .with_fingerprint(provider.fingerprint())
         })
-        .collect::<Vec<Box<dyn GitUrlProvider>>>();
+        .collect()
+}
+
+fn connect(provider: &Provider) -> anyhow::Result<Connection> {
+    // local and command providers never talk to an api
+    let token = &match provider.provider {
+        Type::local | Type::command => String::new(),
+        _ => provider.token()?,
+    };
+    let symbol = &provider.symbol;
+    let protocol = provider.protocol.unwrap_or_default();
+    let mut http = provider.http_settings()?;
+
+    // only Github revalidates its pages, see `HttpSettings::get_page`
+    if provider.provider == Type::github {
+        http = http.with_pages(PageCache::persistent(format!("{}.pages", provider.id())));
+    }
+
+    let git_url_provider: Box<dyn GitUrlProvider> = match provider.provider {
+        Type::github => {
+            let api_url = match &provider.api_url {
+                Some(api_url) => api_url.trim_end_matches('/').to_string(),
+                None => Github::default_api_url(&provider.base_url),
+            };
+            Box::new(
+                Github::new(token.to_owned(), api_url, symbol.to_owned())
+                    .with_sources(
+                        provider
+                            .sources
+                            .to_owned()
+                            .unwrap_or(vec![GithubSource::orgs]),
+                        provider.extra_orgs.to_owned().unwrap_or_default(),
+                        provider.extra_users.to_owned().unwrap_or_default(),
+                    )
+                    .with_protocol(protocol)
+                    .with_http(http.clone()),
+            )
+        }
+
+        Type::bitbucket => {
+            let personal_user = match provider.include_personal {
+                Some(true) => Some(provider.required_username()?.to_owned()),
+                _ => None,
+            };
+            Box::new(
+                Bitbucket::new(
+                    token.to_owned(),
+                    provider.api_url_or(bitbucket::API_PATH),
+                    symbol.to_owned(),
+                )
+                .with_listing(
+                    provider.listing.unwrap_or(BitbucketListing::projects),
+                    personal_user,
+                )
+                .with_protocol(protocol)
+                .with_http(http.clone()),
+            )
+        }
+
+        Type::gitlab => {
+            let gitlab_groups_url = format!(
+                "{}{}",
+                provider.api_url_or(gitlab::API_PATH),
+                gitlab::GROUPS_PATH
+            );
+            Box::new(
+                Gitlab::new(token.to_owned(), gitlab_groups_url, symbol.to_owned())
+                    .with_protocol(protocol)
+                    .with_http(http.clone()),
+            )
+        }
+
+        Type::gitea => Box::new(
+            Gitea::new(
+                token.to_owned(),
+                provider.api_url_or(gitea::API_PATH),
+                symbol.to_owned(),
+            )
+            .with_protocol(protocol)
+            .with_http(http.clone()),
+        ),
+
+        Type::bitbucket_cloud => Box::new(
+            BitbucketCloud::new(
+                provider.required_username()?.to_owned(),
+                token.to_owned(),
+                provider.api_url_or(bitbucket_cloud::API_PATH),
+                symbol.to_owned(),
+            )
+            .with_protocol(protocol)
+            .with_http(http.clone()),
+        ),
+
+        Type::azure_devops => Box::new(
+            AzureDevOps::new(token.to_owned(), provider.api_url_or(""), symbol.to_owned())
+                .with_protocol(protocol)
+                .with_http(http.clone()),
+        ),
 
-    Ok(results)
+        Type::gerrit => {
+            let gerrit_projects_url =
+                format!("{}{}", provider.api_url_or(""), gerrit::PROJECTS_PATH);
+            let ssh_host = provider
+                .ssh_host
+                .to_owned()
+                .unwrap_or_else(|| Gerrit::host_of(&provider.base_url).to_string());
+            Box::new(
+                Gerrit::new(
+                    provider.required_username()?.to_owned(),
+                    token.to_owned(),
+                    gerrit_projects_url,
+                    ssh_host,
+                    provider.ssh_port.unwrap_or(gerrit::DEFAULT_SSH_PORT),
+                    symbol.to_owned(),
+                )
+                .with_protocol(protocol)
+                .with_http(http.clone()),
+            )
+        }
+
+        Type::local => Box::new(Local::new(
+            provider
+                .paths
+                .as_deref()
+                .ok_or_else(|| provider.missing_setting("paths"))?
+                .iter()
+                .map(|path| expand_home(path))
+                .collect(),
+            provider.max_depth.unwrap_or(local::DEFAULT_MAX_DEPTH),
+            symbol.to_owned(),
+        )),
+
+        Type::catalog => Box::new(
+            CatalogProvider::new(
+                provider
+                    .source
+                    .to_owned()
+                    .ok_or_else(|| provider.missing_setting("source"))?,
+                Some(token.to_owned()).filter(|token| !token.is_empty()),
+                symbol.to_owned(),
+            )
+            .with_http(http.clone()),
+        ),
+
+        Type::command => Box::new(CommandProvider::new(
+            provider
+                .command
+                .to_owned()
+                .ok_or_else(|| provider.missing_setting("command"))?,
+            provider.args.to_owned().unwrap_or_default(),
+            symbol.to_owned(),
+        )),
+    };
+
+    let https = match protocol {
+        Protocol::https => Some(HttpsCredentials {
+            username: provider.https_username(),
+            token: Secret::new(token.to_owned()),
+        }),
+        Protocol::ssh => None,
+    };
+
+    let clone_auth = CloneAuth {
+        https,
+        ssh: provider.ssh_auth(),
+    };
+
+    Ok(Connection {
+        provider: git_url_provider,
+        clone_auth,
+        http,
+    })
 }
 
-pub fn run_selector_for_git_urls(clone_urls: Vec<CloneUrl>) -> Vec<std::sync::Arc<dyn SkimItem>> {
-    let urls = clone_urls
-        .iter()
-        .map(|clone_url| {
-            if !clone_url.1.is_empty() {
-                format!("{} | {}", clone_url.1, clone_url.0.to_owned())
-            } else {
-                clone_url.0.to_owned().to_string()
-            }
-        })
-        .collect::<Vec<String>>()
-        .join("\n");
+pub fn run_selector_for_git_urls(clone_urls: Vec<CloneUrl>) -> Vec<Arc<dyn SkimItem>> {
+    let (sender, receiver): (SkimItemSender, SkimItemReceiver) = unbounded();
+
+    send_to_selector(&sender, clone_urls);
+    drop(sender);
+
+    run_selector(receiver)
+}
+
+/// A picker entry showing `symbol | url`, and the repo details in the preview
+/// window while highlighted.
+struct RepoItem {
+    line: String,
+    preview: String,
+}
+
+impl SkimItem for RepoItem {
+    fn text(&self) -> Cow<'_, str> {
+        Cow::Borrowed(&self.line)
+    }
+
+    fn preview(&self, _context: PreviewContext) -> ItemPreview {
+        ItemPreview::Text(self.preview.to_owned())
+    }
+}
+
+pub fn send_to_selector(sender: &SkimItemSender, clone_urls: impl IntoIterator<Item = CloneUrl>) {
+    for clone_url in clone_urls {
+        let preview = preview_text(&clone_url);
+
+        let line = if !clone_url.1.is_empty() {
+            format!("{} | {}", clone_url.1, clone_url.0)
+        } else {
+            clone_url.0
+        };
+
+        // sending only fails once the picker is closed, then nobody is
+        // interested anymore
+        let _ = sender.send(Arc::new(RepoItem { line, preview }));
+    }
+}
+
+/// Lists what the provider told about the repo, omitting everything unknown.
+pub fn preview_text(clone_url: &CloneUrl) -> String {
+    let meta = &clone_url.2;
+
+    let mut lines = vec![meta.full_name.as_ref().unwrap_or(&clone_url.0).to_owned()];
+
+    if let Some(description) = meta.description.as_ref().filter(|d| !d.is_empty()) {
+        lines.push(String::new());
+        lines.push(description.to_owned());
+    }
+
+    let flag = |value: bool| match value {
+        true => "yes".to_string(),
+        false => "no".to_string(),
+    };
+
+    let details = [
+        ("Default branch", meta.default_branch.to_owned()),
+        ("Visibility", meta.visibility.to_owned()),
+        ("Archived", meta.archived.map(flag)),
+        ("Fork", meta.fork.map(flag)),
+        ("Last push", meta.pushed_at.to_owned()),
+        ("Size", meta.size.map(human_size)),
+        (
+            "Tags",
+            Some(meta.tags.join(", ")).filter(|tags| !tags.is_empty()),
+        ),
+    ];
+
+    let known_details = details
+        .into_iter()
+        .filter_map(|(label, value)| value.map(|value| format!("{:<16}{}", label, value)))
+        .collect::<Vec<String>>();
+
+    if !known_details.is_empty() {
+        lines.push(String::new());
+        lines.extend(known_details);
+    }
+
+    lines.join("\n")
+}
 
+fn human_size(kilobytes: u64) -> String {
+    match kilobytes {
+        0..=1023 => format!("{} KB", kilobytes),
+        1024..=1048575 => format!("{:.1} MB", kilobytes as f64 / 1024.0),
+        _ => format!("{:.1} GB", kilobytes as f64 / 1048576.0),
+    }
+}
+
+/// Shows the picker for all items arriving on `items`. Senders may keep adding
+/// items while the picker is open, e.g. from a background refresh.
+pub fn run_selector(items: SkimItemReceiver) -> Vec<Arc<dyn SkimItem>> {
     let options = SkimOptionsBuilder::default()
         .height(Some("100%"))
         .multi(true)
         .exact(true)
+        // an empty command makes skim ask the items for their preview
+        .preview(Some(""))
+        .preview_window(Some("right:40%:wrap"))
         .build()
         .unwrap();
 
-    // `SkimItemReader` is a helper to turn any `BufRead` into a stream of `SkimItem`
-    // `SkimItem` was implemented for `AsRef<str>` by default
-    let item_reader = SkimItemReader::default();
-    let items = item_reader.of_bufread(Cursor::new(urls));
-
     // `run_with` would read and show items from the stream
     Skim::run_with(&options, Some(items))
         .map(|skim_out| match skim_out.final_event {
@@ -92,17 +348,41 @@ pub fn folder_name_for_url(url: &str) -> &str {
     let collection = parts.collect::<Vec<&str>>();
     let git_folder_name = collection.last().unwrap();
 
-    return git_folder_name.strip_suffix(".git").unwrap();
+    // not every provider (e.g. Azure DevOps) appends '.git' to its clone urls
+    git_folder_name
+        .strip_suffix(".git")
+        .unwrap_or(git_folder_name)
 }
 
-pub fn clone_into_folder(git_url: &str, destination_folder: &str) -> anyhow::Result<()> {
+pub fn clone_into_folder(
+    git_url: &str,
+    destination_folder: &str,
+    provider: Option<&ProviderInstance>,
+) -> anyhow::Result<()> {
+    let default_auth = CloneAuth::default();
+    let default_http = HttpSettings::default().with_proxy(ProxyPolicy::from_env());
+
+    let (clone_auth, http) = match provider {
+        Some(provider) => {
+            let connection = provider.connection()?;
+            (&connection.clone_auth, &connection.http)
+        }
+        None => (&default_auth, &default_http),
+    };
+
     let mut callbacks = RemoteCallbacks::new();
-    callbacks
-        .credentials(|_url, _username_from_url, _allowed_types| Cred::ssh_key_from_agent("git"));
+    callbacks.credentials(clone_auth.credentials_callback());
+    callbacks.certificate_check(|cert, host| http.check_certificate(cert, host));
 
     let mut fo = FetchOptions::new();
     fo.remote_callbacks(callbacks);
 
+    if let Some(proxy) = http.proxy.proxy_for(git_url) {
+        let mut proxy_options = ProxyOptions::new();
+        proxy_options.url(proxy.as_str());
+        fo.proxy_options(proxy_options);
+    }
+
     let mut builder = RepoBuilder::new();
     builder.fetch_options(fo);
     builder.clone(git_url, Path::new(destination_folder))?;
@@ -119,7 +399,128 @@ pub fn remove_symbol_prefix(url: &str) -> &str {
 
 #[cfg(test)]
 mod tests {
-    use crate::remove_symbol_prefix;
+    use std::fs::{read_to_string, remove_dir_all};
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    };
+
+    use crate::core::{
+        CloneUrl, GitUrlProvider, HttpProvider, KloniError, ProviderInstance, RepoMeta,
+    };
+    use crate::files::config::Config;
+    use crate::files::{cache_file_age, read_cache_file};
+    use crate::{
+        clone_url_provider_by_config, folder_name_for_url, preview_text, remove_symbol_prefix,
+    };
+
+    #[test]
+    pub fn should_serve_missing_caches_offline_without_connecting_or_creating_them() {
+        let id = format!("kloni-test-offline-{}", std::process::id());
+        let connected = Arc::new(AtomicBool::new(false));
+
+        let provider = ProviderInstance::new(id.to_owned(), {
+            let connected = connected.clone();
+            move || {
+                connected.store(true, Ordering::SeqCst);
+                Err(KloniError::UnknownProvider("offline".to_string()).into())
+            }
+        });
+
+        let clone_urls = provider.collect_cached_clone_urls().unwrap();
+
+        assert!(clone_urls.is_empty());
+        assert!(!connected.load(Ordering::SeqCst));
+        assert!(read_cache_file(&id).is_none());
+        assert!(cache_file_age(&id).is_none());
+    }
+
+    #[test]
+    pub fn should_resolve_credentials_only_once_a_provider_is_contacted() {
+        let dir = std::env::temp_dir().join(format!("kloni-connect-{}", std::process::id()));
+        let _ = remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let runs = dir.join("runs");
+
+        let config = toml::from_str::<Config>(&format!(
+            r#"
+            [[providers]]
+            id = "failing"
+            provider = "github"
+            base_url = "https://ghes.acme.org"
+            token_command = "echo run >> {}; exit 1"
+            symbol = "GH"
+
+            [[providers]]
+            id = "unset-env"
+            provider = "gitlab"
+            base_url = "https://gitlab.acme.org"
+            token_env = "KLONI_TEST_UNSET_TOKEN"
+            ca_bundle = "{}"
+
+            [[providers]]
+            id = "working"
+            provider = "gitea"
+            base_url = "https://gitea.acme.org"
+            token = "s3cr3t"
+
+            [[providers]]
+            id = "local"
+            provider = "local"
+            paths = ["{}"]
+            token_command = "echo run >> {}; exit 1"
+            "#,
+            runs.display(),
+            dir.join("missing.pem").display(),
+            dir.display(),
+            runs.display(),
+        ))
+        .unwrap();
+
+        let providers = clone_url_provider_by_config(&config);
+        let ran_before_contact = runs.exists();
+
+        // the errors the cache fallback and skipping of providers rely on
+        let failing = providers[0].request_groups_from_remote("GH").unwrap_err();
+        let failing_group = providers[0].request_group_from_remote("org", "GH");
+        let failing_again = providers[0].connection().err().unwrap();
+        let unset_env = providers[1].connection().err().unwrap();
+        let working = providers[2].connection().is_ok();
+        let local = providers[3].connection().is_ok();
+
+        let runs = read_to_string(&runs).unwrap();
+        remove_dir_all(&dir).unwrap();
+
+        assert!(!ran_before_contact);
+        assert_eq!(providers[0].symbol(), "GH");
+        assert!(failing
+            .to_string()
+            .starts_with("Could not set up 'failing'"));
+        assert!(matches!(failing_group, Some(Err(_))));
+        assert_eq!(failing.to_string(), failing_again.to_string());
+        assert_eq!(runs.lines().count(), 1);
+        assert!(unset_env
+            .to_string()
+            .contains("'KLONI_TEST_UNSET_TOKEN' holding the token is not set"));
+        assert!(working);
+        // has no api, so its token is never needed
+        assert!(local);
+    }
+
+    #[test]
+    pub fn should_derive_folder_name_with_and_without_git_suffix() {
+        assert_eq!(
+            "example",
+            folder_name_for_url("git@git.acmecorp.com:organization/example.git")
+        );
+
+        assert_eq!(
+            "frontend",
+            folder_name_for_url(
+                "ssh://tfs.acmecorp.com:22/tfs/DefaultCollection/Shop/_git/frontend"
+            )
+        );
+    }
 
     #[test]
     pub fn should_successfully_remove_symbol_from_url() {
@@ -138,4 +539,42 @@ mod tests {
             remove_symbol_prefix("git@git.acmecorp.com:organization/example.git")
         )
     }
+
+    #[test]
+    pub fn should_preview_known_repo_details_only() {
+        let clone_url = CloneUrl::new(
+            "git@github.com:acme/service-api.git".to_string(),
+            "GH".to_string(),
+        )
+        .with_meta(RepoMeta {
+            name: Some("service-api".to_string()),
+            full_name: Some("acme/service-api".to_string()),
+            description: Some("Public api of the shop".to_string()),
+            default_branch: Some("main".to_string()),
+            archived: Some(false),
+            size: Some(5120),
+            tags: vec!["shop".to_string(), "public-api".to_string()],
+            ..RepoMeta::default()
+        });
+
+        assert_eq!(
+            preview_text(&clone_url),
+            "acme/service-api\n\
+             \n\
+             Public api of the shop\n\
+             \n\
+             Default branch  main\n\
+             Archived        no\n\
+             Size            5.0 MB\n\
+             Tags            shop, public-api"
+        );
+
+        assert_eq!(
+            preview_text(&CloneUrl::new(
+                "git@github.com:acme/service-api2.git".to_string(),
+                "".to_string()
+            )),
+            "git@github.com:acme/service-api2.git"
+        );
+    }
 }
@@ -0,0 +1,154 @@
+use std::{
+    fs::read_dir,
+    path::{Path, PathBuf},
+};
+
+use git2::{Repository, RepositoryOpenFlags};
+use url::Url;
+
+use crate::core::{CloneUrl, FileProvider, GitUrlProvider, HttpProvider};
+
+pub struct Local {
+    pub paths: Vec<PathBuf>,
+    pub max_depth: usize,
+    pub symbol: Option<String>,
+}
+
+pub const DEFAULT_MAX_DEPTH: usize = 3;
+
+impl Local {
+    pub fn new(paths: Vec<PathBuf>, max_depth: usize, symbol: Option<String>) -> Local {
+        Local {
+            paths,
+            max_depth,
+            symbol,
+        }
+    }
+
+    pub fn fetch_clone_urls(&self, symbol: &str) -> anyhow::Result<Vec<CloneUrl>> {
+        let mut repo_paths = vec![];
+
+        for path in &self.paths {
+            Self::find_repositories(path, self.max_depth, &mut repo_paths);
+        }
+
+        repo_paths.sort();
+        repo_paths.dedup();
+
+        // repo paths are absolute, only those can be turned into file urls
+        let git_urls = repo_paths
+            .iter()
+            .filter_map(|path| Url::from_file_path(path).ok())
+            .map(|url| CloneUrl::new(url.to_string(), symbol.to_string()))
+            .collect();
+
+        Ok(git_urls)
+    }
+
+    /// Walks `dir` up to `depth` levels and collects every bare repository or
+    /// working copy git2 is able to open. Found repositories are not descended
+    /// into, unreadable directories are skipped.
+    fn find_repositories(dir: &Path, depth: usize, repo_paths: &mut Vec<PathBuf>) {
+        if let Some(repo_path) = Self::open_repository(dir) {
+            repo_paths.push(repo_path);
+            return;
+        }
+
+        if depth == 0 {
+            return;
+        }
+
+        let Ok(entries) = read_dir(dir) else {
+            return;
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+
+            if path.is_dir() {
+                Self::find_repositories(&path, depth - 1, repo_paths);
+            }
+        }
+    }
+
+    fn open_repository(dir: &Path) -> Option<PathBuf> {
+        let is_candidate = dir.join("HEAD").is_file() || dir.join(".git").exists();
+
+        if !is_candidate {
+            return None;
+        }
+
+        let repo =
+            Repository::open_ext(dir, RepositoryOpenFlags::NO_SEARCH, &[] as &[&Path]).ok()?;
+
+        let repo_path = match repo.workdir() {
+            Some(workdir) => workdir.to_path_buf(),
+            None => repo.path().to_path_buf(),
+        };
+
+        Some(repo_path.canonicalize().unwrap_or(repo_path))
+    }
+}
+
+impl HttpProvider for Local {
+    fn request_from_remote(&self, symbol: &str) -> anyhow::Result<Vec<CloneUrl>> {
+        let result = self.fetch_clone_urls(symbol)?;
+        Ok(result)
+    }
+}
+
+impl FileProvider for Local {
+    fn name(&self) -> &str {
+        "local"
+    }
+}
+
+impl GitUrlProvider for Local {
+    fn symbol(&self) -> String {
+        self.symbol.to_owned().unwrap_or("".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::fs::{create_dir_all, remove_dir_all};
+
+    use crate::core::CloneUrl;
+    use crate::local::{Local, DEFAULT_MAX_DEPTH};
+    use git2::Repository;
+    use url::Url;
+
+    #[test]
+    pub fn should_find_bare_mirrors_and_working_copies() {
+        let root = std::env::temp_dir().join(format!("kloni-local-{}", std::process::id()));
+        let _ = remove_dir_all(&root);
+
+        Repository::init_bare(root.join("mirrors/platform/api.git")).unwrap();
+        Repository::init(root.join("checkouts/web")).unwrap();
+        Repository::init(root.join("checkouts/web app")).unwrap();
+        create_dir_all(root.join("mirrors/not-a-repo")).unwrap();
+
+        let root = root.canonicalize().unwrap();
+
+        let local = Local::new(vec![root.clone()], DEFAULT_MAX_DEPTH, None);
+        let cloneable_urls = local.fetch_clone_urls("LO").unwrap();
+
+        remove_dir_all(&root).unwrap();
+
+        let file_url = |path: &str| {
+            let url = Url::from_file_path(root.join(path)).unwrap();
+            CloneUrl::new(url.to_string(), "LO".to_string())
+        };
+
+        assert_eq!(
+            cloneable_urls,
+            vec![
+                file_url("checkouts/web"),
+                file_url("checkouts/web app"),
+                file_url("mirrors/platform/api.git"),
+            ]
+        );
+        assert!(cloneable_urls[1].0.ends_with("/checkouts/web%20app"));
+    }
+}
@@ -1,42 +1,216 @@
-mod files;
-
-use std::path::Path;
+use std::{
+    any::Any,
+    collections::{HashMap, HashSet},
+    path::Path,
+    sync::Mutex,
+    thread,
+};
 
+use clap::{Parser, Subcommand};
 use kloni::{
-    clone_into_folder, clone_url_provider_by_config, core::CloneUrl, files::config::Config,
-    folder_name_for_url, remove_symbol_prefix, run_selector_for_git_urls,
+    clone_into_folder, clone_url_provider_by_config,
+    core::{CloneUrl, FileProvider, GitUrlProvider, KloniError, ProviderInstance},
+    files::{config::Config, migrate_cache_files},
+    folder_name_for_url, remove_symbol_prefix, run_selector, send_to_selector,
 };
+use skim::{prelude::unbounded, SkimItemReceiver, SkimItemSender};
+
+/// Quick git repo cloning cli tool. Without a command the picker is opened.
+#[derive(Parser)]
+#[command(version, args_conflicts_with_subcommands = true)]
+struct Cli {
+    /// Shows the cached repos only, without contacting any provider
+    #[arg(long)]
+    offline: bool,
+
+    #[command(subcommand)]
+    command: Option<Commands>,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Rebuilds the repo caches without opening the picker
+    Refresh {
+        /// Id of the provider to refresh, all providers if omitted
+        #[arg(long)]
+        provider: Option<String>,
+
+        /// Github org or user, or Bitbucket project key to refresh instead of
+        /// the whole provider
+        #[arg(long, requires = "provider")]
+        org: Option<String>,
+    },
+}
 
 fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+
     let conf = Config::get(None)?;
-    let providers = clone_url_provider_by_config(&conf)?;
+    let providers = clone_url_provider_by_config(&conf);
+
+    migrate_cache_files(&conf)?;
+
+    match cli.command {
+        Some(Commands::Refresh { provider, org }) => {
+            refresh(&providers, provider.as_deref(), org.as_deref())
+        }
+        None => pick_and_clone(&providers, cli.offline),
+    }
+}
 
-    let mut selectable_repos: Vec<CloneUrl> = vec![];
+fn refresh(
+    providers: &[ProviderInstance],
+    provider_id: Option<&str>,
+    org: Option<&str>,
+) -> anyhow::Result<()> {
+    let selected = providers
+        .iter()
+        .filter(|provider| provider_id.is_none_or(|id| provider.name() == id))
+        .collect::<Vec<&ProviderInstance>>();
 
-    for provider in providers {
-        let clone_urls = provider.collect_clone_urls()?;
-        selectable_repos.extend(clone_urls)
+    if let (Some(id), true) = (provider_id, selected.is_empty()) {
+        return Err(KloniError::UnknownProvider(id.to_string()).into());
     }
 
-    let selected_items = run_selector_for_git_urls(selectable_repos);
+    let mut failed = vec![];
 
-    for item in selected_items.iter() {
-        let output = item.output().to_string();
+    for provider in selected {
+        let refreshed = match org {
+            Some(org) => provider.refresh_group(org),
+            None => provider.refresh_cache().map(|clone_urls| clone_urls.len()),
+        };
 
-        // probably a very hemdsaermiliche solution but it works
-        let url = remove_symbol_prefix(&output);
-        let folder_name = folder_name_for_url(url);
+        // one failing provider shouldn't keep the others from being refreshed
+        match refreshed {
+            Ok(refreshed) => {
+                println!("Refreshed {} repos of '{}'", refreshed, provider.name())
+            }
+            Err(e) => {
+                eprintln!("Could not refresh '{}': {:#}", provider.name(), e);
+                failed.push(provider.name().to_string());
+            }
+        }
+    }
 
-        if Path::new(folder_name).is_dir() {
-            println!(
-                "Could not clone selection. Folder '{}' already exists.",
-                folder_name
-            );
-        } else {
-            println!("Cloning {} into folder '{}'", &url, folder_name);
-            clone_into_folder(url, folder_name)?;
-            println!("Done!");
+    match failed.is_empty() {
+        true => Ok(()),
+        false => Err(KloniError::RefreshFailed(failed.join("', '")).into()),
+    }
+}
+
+fn pick_and_clone(providers: &[ProviderInstance], offline: bool) -> anyhow::Result<()> {
+    let (sender, receiver): (SkimItemSender, SkimItemReceiver) = unbounded();
+    let provider_of_url: Mutex<HashMap<String, usize>> = Mutex::new(HashMap::new());
+
+    let register = |idx: usize, clone_urls: &[CloneUrl]| {
+        let mut provider_of_url = provider_of_url.lock().unwrap();
+
+        for clone_url in clone_urls.iter() {
+            provider_of_url.insert(clone_url.0.to_owned(), idx);
         }
+    };
+
+    thread::scope(|scope| -> anyhow::Result<()> {
+        let mut refreshes = vec![];
+
+        for (idx, provider) in providers.iter().enumerate() {
+            let collected = match offline {
+                true => provider.collect_cached_clone_urls(),
+                false => provider.collect_clone_urls(),
+            };
+
+            // one unreachable host shouldn't hide the repos of all others
+            let clone_urls = match collected {
+                Ok(clone_urls) => clone_urls,
+                Err(e) => {
+                    eprintln!("Skipping '{}': {}", provider.name(), e);
+                    continue;
+                }
+            };
+            register(idx, &clone_urls);
+
+            if !offline && provider.cache_is_stale() {
+                // the stale list is shown right away, repos that showed up in
+                // the meantime are streamed into the running picker
+                let known_urls: HashSet<String> = clone_urls
+                    .iter()
+                    .map(|clone_url| clone_url.0.to_owned())
+                    .collect();
+                let sender = sender.clone();
+
+                let refresh = scope.spawn(move || -> anyhow::Result<()> {
+                    let fresh_urls = provider
+                        .refresh_cache()?
+                        .into_iter()
+                        .filter(|clone_url| !known_urls.contains(&clone_url.0))
+                        .collect::<Vec<CloneUrl>>();
+
+                    register(idx, &fresh_urls);
+                    send_to_selector(&sender, fresh_urls);
+
+                    Ok(())
+                });
+
+                refreshes.push((provider.name(), refresh));
+            }
+
+            send_to_selector(&sender, clone_urls);
+        }
+
+        drop(sender);
+
+        let selected_items = run_selector(receiver);
+
+        for item in selected_items.iter() {
+            let output = item.output().to_string();
+
+            // probably a very hemdsaermiliche solution but it works
+            let url = remove_symbol_prefix(&output);
+            let folder_name = folder_name_for_url(url);
+
+            if Path::new(folder_name).is_dir() {
+                println!(
+                    "Could not clone selection. Folder '{}' already exists.",
+                    folder_name
+                );
+            } else {
+                println!("Cloning {} into folder '{}'", &url, folder_name);
+                let idx = provider_of_url.lock().unwrap().get(url).copied();
+                clone_into_folder(url, folder_name, idx.map(|idx| &providers[idx]))?;
+                println!("Done!");
+            }
+        }
+
+        for (name, refresh) in refreshes {
+            // the cache is replaced atomically, so aborting the wait is safe
+            if !refresh.is_finished() {
+                eprintln!(
+                    "Waiting for the cache refresh of '{}' to finish, press Ctrl-C to skip it",
+                    name
+                );
+            }
+
+            match refresh.join() {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => eprintln!("Could not refresh the cache of '{}': {}", name, e),
+                Err(panic) => eprintln!(
+                    "The cache refresh of '{}' panicked: {}",
+                    name,
+                    panic_message(panic.as_ref())
+                ),
+            }
+        }
+
+        Ok(())
+    })
+}
+
+/// What a refresh thread panicked with, as far as it can be told.
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    match panic.downcast_ref::<&str>() {
+        Some(message) => message,
+        None => panic
+            .downcast_ref::<String>()
+            .map_or("no message", String::as_str),
     }
-    Ok(())
 }